@@ -0,0 +1,138 @@
+use std::{fmt, fs, path::Path};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use sea_orm::DatabaseConnection;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use crate::{keys, orm, repo::IndexSignature, tuf::Signed};
+
+// how bad an advisory is, ordered from low to critical
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+// known issue of a package, affected lists semver ranges like ">=1.0.0, <1.2.3"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Advisory {
+    pub id: String,
+    pub package: String,
+    pub affected: Vec<String>,
+    pub severity: Severity,
+    pub description: String,
+}
+
+// content of an advisory bundle, signed by its publisher
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdvisoryBundle {
+    pub issued: DateTime<Utc>,
+    pub advisories: Vec<Advisory>,
+}
+
+impl Advisory {
+    fn from_row(row: orm::advisories::Model) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Advisory {
+            id: row.advisory_id,
+            package: row.package,
+            affected: row.affected.split(';').map(String::from).collect(),
+            severity: Severity::from_str(&row.severity, true)?,
+            description: row.description,
+        })
+    }
+
+    fn ranges(&self) -> Result<Vec<VersionReq>, Box<dyn std::error::Error>> {
+        if self.affected.is_empty() {
+            return Err(format!("Advisory {} lists no affected versions", self.id).into());
+        }
+        self.affected.iter()
+            .map(|range| VersionReq::parse(range).map_err(|e| format!("Advisory {}: invalid range {range}: {e}", self.id).into()))
+            .collect()
+    }
+
+    // versions that are not semver never match
+    pub fn affects(&self, name: &str, version: &str) -> bool {
+        let Ok(version) = Version::parse(version) else { return false };
+        name == self.package && self.ranges().is_ok_and(|ranges| ranges.iter().any(|r| r.matches(&version)))
+    }
+}
+
+// sign a JSON list of advisories with the local key, returns the bundle
+pub fn sign_bundle(input: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let advisories: Vec<Advisory> = serde_json::from_slice(&fs::read(input)?).map_err(|e| format!("Invalid advisory list: {e}"))?;
+    for advisory in &advisories {
+        advisory.ranges()?;
+    }
+
+    let bundle = AdvisoryBundle { issued: keys::signing_time(), advisories };
+    let payload = serde_json::to_vec(&bundle)?;
+    let signature = IndexSignature {
+        keyid: keys::key_id(&keys::load_public_key()?)?,
+        sig: general_purpose::STANDARD.encode(keys::sign_bytes(&payload)?),
+        signed_at: None,
+    };
+    Ok(serde_json::to_vec_pretty(&Signed { signed: bundle, signatures: vec![signature] })?)
+}
+
+// import a bundle signed by a trusted key, returns (signer, advisories stored, advisories kept because newer)
+pub async fn import(conn: &DatabaseConnection, path: &Path) -> Result<(String, usize, usize), Box<dyn std::error::Error>> {
+    let signed: Signed<AdvisoryBundle> = serde_json::from_slice(&fs::read(path)?).map_err(|e| format!("Invalid advisory bundle: {e}"))?;
+    let signer = verify_bundle(&signed)?;
+
+    let bundle = signed.signed;
+    for advisory in &bundle.advisories {
+        advisory.ranges()?;
+    }
+
+    let (mut stored, mut kept) = (0, 0);
+    for advisory in bundle.advisories {
+        let row = orm::advisories::Model {
+            id: 0,
+            advisory_id: advisory.id,
+            package: advisory.package,
+            affected: advisory.affected.join(";"),
+            severity: advisory.severity.to_string(),
+            description: advisory.description,
+            signer: signer.clone(),
+            issued_at: bundle.issued,
+        };
+        if orm::advisories_fn::save_advisory(conn, row).await? {
+            stored += 1;
+        } else {
+            kept += 1;
+        }
+    }
+    Ok((signer, stored, kept))
+}
+
+fn verify_bundle(signed: &Signed<AdvisoryBundle>) -> Result<String, Box<dyn std::error::Error>> {
+    let payload = serde_json::to_vec(&signed.signed)?;
+    let trusted = keys::trusted_keys()?;
+    signed.signatures.iter()
+        .find(|s| {
+            let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+            trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &payload, &sig))
+        })
+        .map(|s| s.keyid.clone())
+        .ok_or_else(|| "Advisory bundle has no valid signature from a trusted key".into())
+}
+
+// every imported advisory
+pub async fn load_all(conn: &DatabaseConnection) -> Result<Vec<Advisory>, Box<dyn std::error::Error>> {
+    orm::advisories_fn::list_advisories(conn).await?.into_iter().map(Advisory::from_row).collect()
+}
@@ -0,0 +1,314 @@
+use std::{fs::{self, File}, io::{Cursor, Read, Write}, path::{Component, Path, PathBuf}};
+use clap::ValueEnum;
+use walkdir::{DirEntry, WalkDir};
+use zip::{write::FileOptions, CompressionMethod};
+use crate::manifest::is_meta;
+
+const ZSTD_LEVEL: i32 = 9;
+const XZ_LEVEL: u32 = 6;
+// file type bits of a unix mode, as zip stores them
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+// archive formats supported inside a .pkg container
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// zip archive compressed with deflate
+    Zip,
+    /// tar archive compressed with zstd
+    #[value(name = "tar.zst")]
+    TarZst,
+    /// tar archive compressed with xz
+    #[value(name = "tar.xz")]
+    TarXz,
+    /// zip archive without compression
+    Stored,
+}
+
+impl ArchiveFormat {
+    // id saved in the container header
+    pub fn id(self) -> u8 {
+        match self {
+            ArchiveFormat::Zip => 1,
+            ArchiveFormat::TarZst => 2,
+            ArchiveFormat::TarXz => 3,
+            ArchiveFormat::Stored => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(ArchiveFormat::Zip),
+            2 => Some(ArchiveFormat::TarZst),
+            3 => Some(ArchiveFormat::TarXz),
+            4 => Some(ArchiveFormat::Stored),
+            _ => None,
+        }
+    }
+
+    // file extension of the plain archive
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip | ArchiveFormat::Stored => "zip",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::TarXz => "tar.xz",
+        }
+    }
+}
+
+impl std::fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ArchiveFormat::Zip => "zip/deflate",
+            ArchiveFormat::TarZst => "tar+zstd",
+            ArchiveFormat::TarXz => "tar+xz",
+            ArchiveFormat::Stored => "zip/stored",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// file or dir read from an archive
+#[derive(Debug)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub mode: u32,
+    pub is_dir: bool,
+    pub data: Vec<u8>,
+}
+
+// compress src_dir into dst_file using the selected format, extra entries are generated metadata
+pub fn pack_dir(src_dir: &Path, dst_file: &Path, format: ArchiveFormat, extra: &[ArchiveEntry]) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        ArchiveFormat::Zip => zip_dir(src_dir, dst_file, CompressionMethod::Deflated, extra)?,
+        ArchiveFormat::Stored => zip_dir(src_dir, dst_file, CompressionMethod::Stored, extra)?,
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::Encoder::new(File::create(dst_file)?, ZSTD_LEVEL)?;
+            tar_dir(src_dir, encoder, extra)?.finish()?;
+        }
+        ArchiveFormat::TarXz => {
+            let encoder = xz2::write::XzEncoder::new(File::create(dst_file)?, XZ_LEVEL);
+            tar_dir(src_dir, encoder, extra)?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+// source entries to pack, the reserved metadata dir is never taken from the source
+// symlinks are refused, packing one would store an empty file or whatever it points at
+pub fn source_entries(src_dir: &Path) -> std::io::Result<Vec<(DirEntry, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(src_dir).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        let name = entry.path().strip_prefix(src_dir).unwrap().to_path_buf(); // keep path structure and use a relative way
+        if is_meta(&name.to_string_lossy()) {
+            continue;
+        }
+        if entry.depth() > 0 && entry.path_is_symlink() {
+            return Err(std::io::Error::other(format!("{} is a symlink, packages cannot contain symlinks", entry.path().display())));
+        }
+        entries.push((entry, name));
+    }
+    Ok(entries)
+}
+
+// to compress dirs in .zip
+fn zip_dir(src_dir: &Path, dst_file: &Path, method: CompressionMethod, extra: &[ArchiveEntry]) -> zip::result::ZipResult<()> {
+    let file = File::create(dst_file)?; // Create file
+    let mut zip = zip::ZipWriter::new(file); // zip writer
+
+    // travel dirs and subdirs in src_dir
+    for (entry, name) in source_entries(src_dir)? {
+        let path = entry.path();
+        // file options, keeping original permissions
+        let options = FileOptions::default()
+            .compression_method(method)
+            .unix_permissions(file_mode(path));
+
+        if entry.file_type().is_file() { // if is file:
+            zip.start_file(name.to_string_lossy(), options)?; // create file
+            let mut f = File::open(path)?;
+            std::io::copy(&mut f, &mut zip)?; // copy its content in zip archive
+        } else if !name.as_os_str().is_empty() { // if is folder:
+            zip.add_directory(name.to_string_lossy(), options)?; // add a dir to zip
+        }
+    }
+
+    for entry in extra {
+        let options = FileOptions::default()
+            .compression_method(method)
+            .unix_permissions(entry.mode);
+        zip.start_file(entry.path.as_str(), options)?;
+        zip.write_all(&entry.data)?;
+    }
+    zip.finish()?; // close zip
+    Ok(())
+}
+
+// to write dirs in a tar stream, returns the inner writer to finish compression
+fn tar_dir<W: Write>(src_dir: &Path, writer: W, extra: &[ArchiveEntry]) -> std::io::Result<W> {
+    let mut tar = tar::Builder::new(writer);
+    tar.follow_symlinks(false);
+
+    for (entry, name) in source_entries(src_dir)? {
+        if entry.file_type().is_file() {
+            tar.append_path_with_name(entry.path(), &name)?;
+        } else if !name.as_os_str().is_empty() {
+            tar.append_dir(&name, entry.path())?;
+        }
+    }
+
+    for entry in extra {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(entry.data.len() as u64);
+        header.set_mode(entry.mode);
+        header.set_mtime(chrono::Utc::now().timestamp() as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, &entry.path, entry.data.as_slice())?;
+    }
+    tar.into_inner()
+}
+
+#[cfg(unix)]
+pub fn file_mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).map(|m| m.permissions().mode() & 0o7777).unwrap_or(0o755)
+}
+
+#[cfg(not(unix))]
+pub fn file_mode(_path: &Path) -> u32 {
+    0o755
+}
+
+// read every entry of an archive held in memory
+pub fn read_entries(data: &[u8], format: ArchiveFormat) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
+    match format {
+        ArchiveFormat::Zip | ArchiveFormat::Stored => read_zip(data),
+        ArchiveFormat::TarZst => read_tar(zstd::Decoder::new(data)?),
+        ArchiveFormat::TarXz => read_tar(xz2::read::XzDecoder::new(data)),
+    }
+}
+
+fn read_zip(data: &[u8]) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut entries = Vec::with_capacity(zip.len());
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let is_dir = file.is_dir();
+        let path = file.name().trim_end_matches('/').to_string();
+        let mode = file.unix_mode().unwrap_or(0o755);
+        if mode & S_IFMT == S_IFLNK {
+            return Err(format!("Symlink {path} in archive is not supported").into());
+        }
+        let mode = mode & 0o7777;
+
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        entries.push(ArchiveEntry { path, mode, is_dir, data });
+    }
+    Ok(entries)
+}
+
+fn read_tar<R: Read>(reader: R) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
+    let mut tar = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        let is_dir = kind.is_dir();
+        let path = entry.path()?.to_string_lossy().trim_end_matches('/').to_string();
+        // links and special files would be written out as empty regular files
+        if !is_dir && !kind.is_file() {
+            return Err(format!("{kind:?} entry {path} in archive is not supported").into());
+        }
+        let mode = entry.header().mode()? & 0o7777;
+
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push(ArchiveEntry { path, mode, is_dir, data });
+    }
+    Ok(entries)
+}
+
+// write archive entries into dest, refusing paths that escape it
+pub fn extract<'a>(entries: impl IntoIterator<Item = &'a ArchiveEntry>, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dirs = Vec::new();
+
+    for entry in entries {
+        let target = dest.join(safe_path(&entry.path)?);
+
+        if entry.is_dir {
+            fs::create_dir_all(&target)?;
+            dirs.push((target, entry.mode)); // set dir modes once files are written
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, &entry.data)?;
+            set_mode(&target, entry.mode)?;
+        }
+    }
+
+    for (dir, mode) in dirs.iter().rev() {
+        set_mode(dir, *mode)?;
+    }
+    Ok(())
+}
+
+// only keep normal components of an archive path
+pub fn safe_path(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(format!("Unsafe path in archive: {name}").into()),
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::hash_dir, testutil::temp_dir};
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_refused_when_packing_hashing_and_reading() {
+        let outside = temp_dir("outside").join("secret.key");
+        fs::write(&outside, "secret").unwrap();
+        let src = temp_dir("src");
+        fs::write(src.join("hello.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(&outside, src.join("secret")).unwrap();
+
+        for format in [ArchiveFormat::Zip, ArchiveFormat::Stored, ArchiveFormat::TarZst, ArchiveFormat::TarXz] {
+            let dst = temp_dir("packed").join(format!("pkg.{}", format.extension()));
+            let err = pack_dir(&src, &dst, format, &[]).unwrap_err().to_string();
+            assert!(err.contains("symlink"), "{format}: {err}");
+        }
+        let err = hash_dir(&src).unwrap_err().to_string();
+        assert!(err.contains("symlink"), "{err}");
+
+        // links made by other tools are not read back as empty files
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        tar.append_link(&mut header, "secret", &outside).unwrap();
+        let data = zstd::encode_all(tar.into_inner().unwrap().as_slice(), 0).unwrap();
+        assert!(read_entries(&data, ArchiveFormat::TarZst).is_err());
+    }
+}
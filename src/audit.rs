@@ -0,0 +1,149 @@
+use std::{fs, path::{Path, PathBuf}};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use crate::{advisory::{self, Severity}, orm};
+
+// installed or locked version matched by an advisory
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    pub advisory: String,
+    pub package: String,
+    pub version: String,
+    pub source: String,
+    pub severity: Severity,
+    pub description: String,
+}
+
+// result of an audit, printed as is with --json
+#[derive(Serialize, Debug)]
+pub struct AuditReport {
+    pub advisories: usize,
+    pub checked: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    // findings at or above a severity
+    pub fn failing(&self, threshold: Severity) -> usize {
+        self.findings.iter().filter(|f| f.severity >= threshold).count()
+    }
+
+    pub fn print(&self) {
+        if self.findings.is_empty() {
+            println!("✅ {} package versions checked against {} advisories, nothing affected", self.checked, self.advisories);
+            return;
+        }
+        for f in &self.findings {
+            println!("🚨 [{}] {} {} ({}): {} {}", f.severity, f.package, f.version, f.source, f.advisory, f.description);
+        }
+        println!("❌ {} finding(s) in {} package versions checked against {} advisories", self.findings.len(), self.checked, self.advisories);
+    }
+}
+
+// lockfile layout, the [[package]] tables of securepkg or Cargo lockfiles
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+}
+
+// match installed versions and every lockfile against the imported advisories
+pub async fn audit(conn: &DatabaseConnection, lockfiles: &[PathBuf]) -> Result<AuditReport, Box<dyn std::error::Error>> {
+    let advisories = advisory::load_all(conn).await?;
+
+    let mut targets: Vec<(String, String, String)> = orm::installed_fn::list_installed(conn).await?
+        .into_iter()
+        .map(|pkg| (pkg.name, pkg.version, "installed".to_string()))
+        .collect();
+    for path in lockfiles {
+        targets.extend(read_lockfile(path)?.into_iter().map(|(name, version)| (name, version, path.display().to_string())));
+    }
+
+    let mut findings = Vec::new();
+    for (name, version, source) in &targets {
+        for advisory in advisories.iter().filter(|a| a.affects(name, version)) {
+            findings.push(Finding {
+                advisory: advisory.id.clone(),
+                package: name.clone(),
+                version: version.clone(),
+                source: source.clone(),
+                severity: advisory.severity,
+                description: advisory.description.clone(),
+            });
+        }
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.package.cmp(&b.package)));
+
+    Ok(AuditReport { advisories: advisories.len(), checked: targets.len(), findings })
+}
+
+fn read_lockfile(path: &Path) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let lockfile: Lockfile = toml::from_str(&fs::read_to_string(path)?).map_err(|e| format!("Invalid lockfile {}: {e}", path.display()))?;
+    Ok(lockfile.package.into_iter().map(|p| (p.name, p.version)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{advisory::Advisory, testutil};
+
+    fn advisory(id: &str, package: &str, affected: &[&str], severity: Severity) -> Advisory {
+        Advisory {
+            id: id.into(),
+            package: package.into(),
+            affected: affected.iter().map(|r| r.to_string()).collect(),
+            severity,
+            description: format!("{id} issue"),
+        }
+    }
+
+    #[test]
+    fn advisories_match_their_version_ranges_only() {
+        let a = advisory("ADV-1", "aud-lib", &[">=1.0.0, <1.2.3", "^2.1"], Severity::High);
+        for version in ["1.0.0", "1.2.2", "2.1.0", "2.9.9"] {
+            assert!(a.affects("aud-lib", version), "{version}");
+        }
+        for version in ["0.9.9", "1.2.3", "2.0.5", "3.0.0", "not-semver"] {
+            assert!(!a.affects("aud-lib", version), "{version}");
+        }
+        assert!(!a.affects("aud-other", "1.1.0"));
+    }
+
+    #[tokio::test]
+    async fn fail_on_counts_findings_at_or_above_the_threshold() {
+        testutil::home();
+        let conn = testutil::db().await;
+        let dir = testutil::temp_dir("audit");
+        let list = vec![
+            advisory("ADV-LOW", "aud-a", &["<2"], Severity::Low),
+            advisory("ADV-HIGH", "aud-b", &[">=1.0.0, <1.5.0"], Severity::High),
+            advisory("ADV-CRIT", "aud-c", &["=3.0.0"], Severity::Critical),
+            advisory("ADV-FIXED", "aud-c", &["<3.0.0"], Severity::Critical),
+        ];
+        fs::write(dir.join("advisories.json"), serde_json::to_vec(&list).unwrap()).unwrap();
+        fs::write(dir.join("bundle.json"), advisory::sign_bundle(&dir.join("advisories.json")).unwrap()).unwrap();
+        advisory::import(&conn, &dir.join("bundle.json")).await.unwrap();
+
+        let lockfile = dir.join("securepkg.lock");
+        fs::write(&lockfile, "[[package]]\nname = \"aud-a\"\nversion = \"1.0.0\"\n\n[[package]]\nname = \"aud-b\"\nversion = \"1.4.0\"\n\n[[package]]\nname = \"aud-c\"\nversion = \"3.0.0\"\n").unwrap();
+        let report = audit(&conn, &[lockfile]).await.unwrap();
+        let found: Vec<&str> = report.findings.iter().map(|f| f.advisory.as_str()).collect();
+        assert_eq!(found, ["ADV-CRIT", "ADV-HIGH", "ADV-LOW"]);
+
+        // any failing finding exits with 1
+        assert_eq!(report.failing(Severity::Low), 3);
+        assert_eq!(report.failing(Severity::Medium), 2);
+        assert_eq!(report.failing(Severity::High), 2);
+        assert_eq!(report.failing(Severity::Critical), 1);
+
+        // an unreadable lockfile is an audit error, exit 2
+        fs::write(dir.join("broken.lock"), "[[package]\n").unwrap();
+        assert!(audit(&conn, &[dir.join("broken.lock")]).await.is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use clap::ValueEnum;
+use rand::Rng;
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+use crate::orm::{self, tokens::Model};
+
+// permissions a registry api token can hold, admin implies every other scope
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Scope {
+    Publish,
+    Yank,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Publish => "publish",
+            Scope::Yank => "yank",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// create a token, the plain value is only returned here
+pub async fn create_token(conn: &DatabaseConnection, name: &str, scopes: &[Scope]) -> Result<String, Box<dyn std::error::Error>> {
+    if scopes.is_empty() {
+        return Err("A token needs at least one scope".into());
+    }
+
+    let mut secret = [0u8; 32];
+    rand::rng().fill(&mut secret);
+    let token = format!("spk_{}", hex::encode(secret));
+
+    let scopes = scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",");
+    orm::tokens_fn::insert_token(conn, name, hash_token(&token), scopes).await?;
+    Ok(token)
+}
+
+// why a request was refused
+pub enum AuthError {
+    // no token or an unknown one
+    Unauthenticated(String),
+    // valid token without the needed scope
+    Forbidden(String),
+}
+
+// find the token of an Authorization header and check it holds scope
+pub async fn authorize(conn: &DatabaseConnection, authorization: Option<&str>, scope: Scope) -> Result<Model, AuthError> {
+    let token = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| AuthError::Unauthenticated("Missing bearer token".into()))?;
+
+    let found = orm::tokens_fn::find_by_hash(conn, &hash_token(token.trim()))
+        .await
+        .map_err(|e| AuthError::Unauthenticated(format!("DB error: {e}")))?
+        .ok_or_else(|| AuthError::Unauthenticated("Unknown token".into()))?;
+
+    let allowed = found.scopes.split(',').any(|s| s == scope.as_str() || s == Scope::Admin.as_str());
+    if allowed {
+        Ok(found)
+    } else {
+        Err(AuthError::Forbidden(format!("Token {} lacks the {} scope", found.name, scope.as_str())))
+    }
+}
@@ -0,0 +1,108 @@
+use std::{io::ErrorKind, path::{Path, PathBuf}};
+use async_trait::async_trait;
+use tokio::fs;
+use crate::{archive, blob::BlobStore};
+
+// blobs as files of a directory, ~/.securepkg/packages by default
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: PathBuf) -> Self {
+        LocalStore { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(self.root.join(archive::safe_path(key)?))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalStore {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    // written next to the target and renamed so readers never see partial blobs
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.path(key)?;
+        fs::create_dir_all(&self.root).await?;
+        let tmp_path = path.with_file_name(format!(".{key}.tmp"));
+        fs::write(&tmp_path, data).await?;
+        fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let path = self.path(key)?;
+        match fs::read(path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let path = self.path(key)?;
+        Ok(fs::try_exists(path).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.path(key)?;
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // temporary files of unfinished writes are left out
+    async fn list(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut keys = Vec::new();
+        let mut entries = match fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(keys),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let key = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().await?.is_file() && !key.starts_with('.') {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let store = LocalStore::new(testutil::temp_dir("blobs").join("packages"));
+        assert_eq!(store.list().await.unwrap(), Vec::<String>::new());
+
+        store.put("a-1.0.0.pkg", b"package".to_vec()).await.unwrap();
+        store.put("a-1.0.0.sig", b"signature".to_vec()).await.unwrap();
+        store.put("a-1.0.0.pkg", b"rebuilt".to_vec()).await.unwrap();
+        std::fs::write(store.root.join(".b-1.0.0.pkg.tmp"), b"unfinished").unwrap();
+
+        assert_eq!(store.get("a-1.0.0.pkg").await.unwrap().unwrap(), b"rebuilt");
+        assert_eq!(store.get("missing.pkg").await.unwrap(), None);
+        assert!(store.exists("a-1.0.0.sig").await.unwrap());
+        assert!(!store.exists("missing.pkg").await.unwrap());
+        assert_eq!(store.list().await.unwrap(), ["a-1.0.0.pkg", "a-1.0.0.sig"]);
+        assert!(store.put("../escape.pkg", Vec::new()).await.is_err());
+
+        store.delete("a-1.0.0.sig").await.unwrap();
+        store.delete("a-1.0.0.sig").await.unwrap(); // deleting twice is fine
+        assert_eq!(store.list().await.unwrap(), ["a-1.0.0.pkg"]);
+    }
+}
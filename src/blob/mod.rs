@@ -0,0 +1,102 @@
+pub mod local;
+pub mod s3;
+
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+use async_trait::async_trait;
+use crate::{config::{Config, StorageConfig}, provenance::PROVENANCE_EXTENSION, repo::MANIFEST_SIDECAR, storage, translog::{LOG_FILE, PROOF_EXTENSION}};
+
+// where package blobs (.pkg, .sig, manifest sidecar) are kept
+// keys are flat file names such as <name>-<version>.pkg
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    // human readable location, for messages
+    fn describe(&self) -> String;
+
+    // directory holding the blobs when they are plain local files
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), Box<dyn std::error::Error>>;
+
+    // None when the key does not exist
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>>;
+
+    async fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.get(key).await?.is_some())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    // every key of the store, sorted
+    async fn list(&self) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+}
+
+// backend selected by the [storage] table of config.toml
+pub fn open() -> Result<Arc<dyn BlobStore>, Box<dyn std::error::Error>> {
+    Ok(match Config::load()?.storage {
+        StorageConfig::Local { path } => Arc::new(local::LocalStore::new(path.unwrap_or_else(storage::get_pkg_dir))),
+        StorageConfig::S3 { endpoint, bucket, region, prefix, access_key, secret_key } => {
+            let access_key = access_key.or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())
+                .ok_or("S3 storage needs access_key or AWS_ACCESS_KEY_ID")?;
+            let secret_key = secret_key.or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())
+                .ok_or("S3 storage needs secret_key or AWS_SECRET_ACCESS_KEY")?;
+            Arc::new(s3::S3Store::new(&endpoint, bucket, region, prefix, access_key, secret_key)?)
+        }
+    })
+}
+
+pub fn pkg_key(name: &str, version: &str) -> String {
+    format!("{name}-{version}.pkg")
+}
+
+// key of a package row, rows written before storage backends hold a full path
+pub fn key_of(encrypted_path: &str) -> String {
+    Path::new(encrypted_path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+// copy every blob of one store into another, returns how many were copied
+pub async fn copy_all(from: &dyn BlobStore, to: &dyn BlobStore) -> Result<usize, Box<dyn std::error::Error>> {
+    let keys = from.list().await?;
+    for key in &keys {
+        let data = from.get(key).await?.ok_or_else(|| format!("{key} disappeared from {}", from.describe()))?;
+        to.put(key, data).await?;
+    }
+    Ok(keys.len())
+}
+
+// key of the .sig or manifest sidecar stored next to a package
+pub fn sibling(key: &str, extension: &str) -> String {
+    Path::new(key).with_extension(extension).to_string_lossy().to_string()
+}
+
+// local path of a package for code reading files, None if the key does not exist
+// remote blobs are copied with their signature, manifest, proof and provenance into the cache
+pub async fn pkg_path(store: &dyn BlobStore, key: &str) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if let Some(root) = store.local_root() {
+        let path = root.join(key);
+        return Ok(path.exists().then_some(path));
+    }
+
+    let Some(data) = store.get(key).await? else {
+        return Ok(None);
+    };
+    let cache_dir = storage::get_cache_dir().join("blobs");
+    fs::create_dir_all(&cache_dir)?;
+    let path = cache_dir.join(key);
+    fs::write(&path, data)?;
+
+    for extension in ["sig", MANIFEST_SIDECAR, PROOF_EXTENSION, PROVENANCE_EXTENSION] {
+        let sibling_path = path.with_extension(extension);
+        match store.get(&sibling(key, extension)).await? {
+            Some(data) => fs::write(&sibling_path, data)?,
+            None if sibling_path.exists() => fs::remove_file(&sibling_path)?,
+            None => {}
+        }
+    }
+    // the log the proof refers to
+    if let Some(data) = store.get(LOG_FILE).await? {
+        fs::write(cache_dir.join(LOG_FILE), data)?;
+    }
+    Ok(Some(path))
+}
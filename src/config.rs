@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::storage;
+
+// user settings stored in ~/.securepkg/config.toml
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub repositories: Vec<Repository>,
+    #[serde(default)]
+    pub registries: Vec<Registry>,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub signature_policies: Vec<SignaturePolicy>,
+    #[serde(default)]
+    pub tuf: TufConfig,
+}
+
+// threshold of signatures a package needs, e.g. 2 of 3 maintainer keys
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignaturePolicy {
+    // package name, "*" for every package without its own policy
+    pub package: String,
+    pub keys: Vec<String>,
+    pub threshold: usize,
+}
+
+// metadata database, the [database] table of config.toml
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DatabaseConfig {
+    // sqlite:// or postgres:// url, defaults to ~/.securepkg/db.sqlite
+    pub url: Option<String>,
+}
+
+// lifetimes in days of the TUF metadata of export folders, the [tuf] table of config.toml
+// export folders are only re-signed by publish, export, yank and repo refresh, `serve` re-signs on requests
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TufConfig {
+    pub export_timestamp_days: Option<i64>,
+    pub export_snapshot_days: Option<i64>,
+}
+
+// package repository, a local dir or file:// url
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Repository {
+    pub name: String,
+    pub url: String,
+}
+
+// `serve` instance packages are published to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Registry {
+    pub name: String,
+    pub url: String,
+    pub token: Option<String>,
+}
+
+// where package blobs are kept, the [storage] table of config.toml
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StorageConfig {
+    // defaults to ~/.securepkg/packages
+    Local {
+        path: Option<PathBuf>,
+    },
+    // S3-compatible object store (AWS, MinIO), credentials default to AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY
+    S3 {
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        #[serde(default)]
+        prefix: String,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+    },
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig::Local { path: None }
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = storage::get_config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        toml::from_str(&fs::read_to_string(&path)?).map_err(|e| format!("Invalid config {}: {e}", path.display()).into())
+    }
+
+    pub fn registry(&self, name: &str) -> Result<&Registry, Box<dyn std::error::Error>> {
+        self.registries.iter().find(|r| r.name == name).ok_or_else(|| format!("Registry {name} is not configured").into())
+    }
+
+    // written next to the config and renamed over it, readers never see half a file
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = storage::get_config_path();
+        let tmp_path = path.with_extension("toml.tmp");
+        fs::write(&tmp_path, toml::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
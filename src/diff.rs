@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use similar::TextDiff;
+use crate::{archive::ArchiveEntry, manifest::{self, FileEntry, Manifest}, package::{locate_pkg, open_pkg}};
+
+// text diffs are only shown for files up to this size
+const MAX_TEXT_DIFF: usize = 64 * 1024;
+
+// compare two versions of a package without installing them
+pub async fn diff_pkg(name: &str, old_version: &str, new_version: &str, text: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, old_entries) = open_pkg(name, &locate_pkg(name, old_version, None).await?)?;
+    let (_, new_entries) = open_pkg(name, &locate_pkg(name, new_version, None).await?)?;
+
+    println!("🔍 {name}: {old_version} -> {new_version}");
+    diff_manifest(
+        &Manifest::from_entries(&old_entries)?.unwrap_or_default(),
+        &Manifest::from_entries(&new_entries)?.unwrap_or_default(),
+    );
+
+    let old_files = files_by_path(&old_entries);
+    let new_files = files_by_path(&new_entries);
+
+    let mut changes = 0;
+    for (path, old) in &old_files {
+        match new_files.get(path) {
+            None => {
+                println!("➖ removed: {path} ({} bytes)", old.size);
+                changes += 1;
+            }
+            Some(new) if new != old => {
+                let mut what = Vec::new();
+                if new.sha256 != old.sha256 {
+                    what.push(format!("content {} -> {} bytes", old.size, new.size));
+                }
+                if new.mode != old.mode {
+                    what.push(format!("mode {:o} -> {:o}", old.mode, new.mode));
+                }
+                println!("✏️ modified: {path} ({})", what.join(", "));
+                changes += 1;
+
+                if text && new.sha256 != old.sha256 {
+                    print_text_diff(path, &old_entries, &new_entries);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, new) in &new_files {
+        if !old_files.contains_key(path) {
+            println!("➕ added: {path} ({} bytes)", new.size);
+            changes += 1;
+        }
+    }
+
+    if changes == 0 {
+        println!("✅ No file changes");
+    } else {
+        println!("📊 {changes} file(s) changed");
+    }
+    Ok(())
+}
+
+fn files_by_path(entries: &[ArchiveEntry]) -> BTreeMap<String, FileEntry> {
+    manifest::payload_files(entries).into_iter().map(|f| (f.path.clone(), f)).collect()
+}
+
+fn diff_manifest(old: &Manifest, new: &Manifest) {
+    let fields = [
+        ("author", &old.author, &new.author),
+        ("description", &old.description, &new.description),
+        ("license", &old.license, &new.license),
+    ];
+    for (field, old, new) in fields {
+        if old != new {
+            println!("📄 {field}: {} -> {}", old.as_deref().unwrap_or("-"), new.as_deref().unwrap_or("-"));
+        }
+    }
+
+    for (dep, req) in &old.dependencies {
+        match new.dependencies.get(dep) {
+            None => println!("➖ dependency removed: {dep} {req}"),
+            Some(new_req) if new_req != req => println!("✏️ dependency changed: {dep} {req} -> {new_req}"),
+            Some(_) => {}
+        }
+    }
+    for (dep, req) in &new.dependencies {
+        if !old.dependencies.contains_key(dep) {
+            println!("➕ dependency added: {dep} {req}");
+        }
+    }
+}
+
+// unified diff for small utf-8 files
+fn print_text_diff(path: &str, old_entries: &[ArchiveEntry], new_entries: &[ArchiveEntry]) {
+    let find = |entries: &[ArchiveEntry]| {
+        entries.iter()
+            .find(|e| e.path == path)
+            .filter(|e| e.data.len() <= MAX_TEXT_DIFF)
+            .and_then(|e| String::from_utf8(e.data.clone()).ok())
+    };
+
+    match (find(old_entries), find(new_entries)) {
+        (Some(old), Some(new)) => {
+            let diff = TextDiff::from_lines(&old, &new);
+            print!("{}", diff.unified_diff().context_radius(3).header(&format!("a/{path}"), &format!("b/{path}")));
+        }
+        _ => println!("   (binary or large file, no text diff)"),
+    }
+}
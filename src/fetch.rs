@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+use reqwest::{StatusCode, header};
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+use crate::storage;
+
+// GET a small document fully into memory
+pub async fn fetch_bytes(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let resp = reqwest::get(url).await?;
+    if !resp.status().is_success() {
+        return Err(format!("GET {url} failed: {}", resp.status()).into());
+    }
+    Ok(resp.bytes().await?.to_vec())
+}
+
+// like fetch_bytes, None when the server answers 404
+pub async fn fetch_optional(url: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let resp = reqwest::get(url).await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        return Err(format!("GET {url} failed: {}", resp.status()).into());
+    }
+    Ok(Some(resp.bytes().await?.to_vec()))
+}
+
+// download a package into the cache, keyed by its expected hash
+// progress goes to stderr so JSON output stays clean
+// a leftover .part file is resumed with a Range request
+pub async fn fetch_cached(url: &str, sha256: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid package hash in index: {sha256}").into());
+    }
+
+    let cache_dir = storage::get_cache_dir();
+    fs::create_dir_all(&cache_dir).await?;
+    let cached = cache_dir.join(format!("{sha256}.pkg"));
+
+    if cached.exists() && hash_file(&cached).await? == sha256 {
+        eprintln!("💾 Using cached download {}", cached.display());
+        return Ok(cached);
+    }
+
+    let part = cache_dir.join(format!("{sha256}.pkg.part"));
+    download_resumable(url, &part).await?;
+
+    if hash_file(&part).await? != sha256 {
+        fs::remove_file(&part).await?;
+        return Err(format!("Downloaded {url} does not match the index hash").into());
+    }
+    fs::rename(&part, &cached).await?;
+    Ok(cached)
+}
+
+async fn download_resumable(url: &str, part: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut offset = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+    let client = reqwest::Client::new();
+    let mut resp = get_from(&client, url, offset).await?;
+    // a range starting anywhere else would be appended at the wrong place, start over without one
+    if resp.status() == StatusCode::PARTIAL_CONTENT && offset > 0 && range_start(&resp) != Some(offset) {
+        eprintln!("⚠️ {url} did not resume at byte {offset}, downloading it again");
+        offset = 0;
+        resp = get_from(&client, url, offset).await?;
+    }
+
+    let mut file = match resp.status() {
+        StatusCode::PARTIAL_CONTENT if range_start(&resp) != Some(offset) => {
+            return Err(format!("GET {url} answered a range that does not start at byte {offset}").into());
+        }
+        StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            eprintln!("⏯️ Resuming download at byte {offset}");
+            fs::OpenOptions::new().append(true).open(part).await?
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()), // already complete
+        status if status.is_success() => fs::File::create(part).await?, // server ignored the range or there was none
+        status => return Err(format!("GET {url} failed: {status}").into()),
+    };
+
+    eprintln!("⬇️ Downloading {url}");
+    // what arrived before a short read is flushed too, the next attempt resumes after it
+    let received = async {
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        Ok::<(), Box<dyn std::error::Error>>(())
+    }.await;
+    file.flush().await?;
+    received
+}
+
+// GET url from byte offset on
+async fn get_from(client: &reqwest::Client, url: &str, offset: u64) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={offset}-"));
+    }
+    Ok(request.send().await?)
+}
+
+// first byte of a 206 answer, from its Content-Range header
+fn range_start(resp: &reqwest::Response) -> Option<u64> {
+    let range = resp.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes ")?.split_once('-')?.0.trim().parse().ok()
+}
+
+async fn hash_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(hex::encode(Sha256::digest(fs::read(path).await?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use axum::{Router, extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse, routing::get};
+    use rand::Rng;
+    use tokio::io::AsyncWriteExt;
+    use crate::{config::Repository, repo::{Index, IndexEntry, LoadedRepo, RepoSource}, testutil};
+
+    // file server recording the Range header of every request
+    #[derive(Clone)]
+    struct Mock {
+        data: Arc<Vec<u8>>,
+        ranges: Arc<Mutex<Vec<Option<String>>>>,
+        // ranges are answered this many bytes before the requested start
+        shift: usize,
+    }
+
+    async fn serve_file(State(mock): State<Mock>, headers: HeaderMap) -> axum::response::Response {
+        let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok()).map(str::to_string);
+        mock.ranges.lock().unwrap().push(range.clone());
+        let total = mock.data.len();
+        match range.and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok()) {
+            Some(start) if start >= total => (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, format!("bytes */{total}"))]).into_response(),
+            Some(start) => {
+                let start = start.saturating_sub(mock.shift);
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    [(header::CONTENT_RANGE, format!("bytes {start}-{}/{total}", total - 1))],
+                    mock.data[start..].to_vec(),
+                ).into_response()
+            }
+            None => mock.data.to_vec().into_response(),
+        }
+    }
+
+    async fn mock(data: Vec<u8>) -> (String, Mock) {
+        shifted_mock(data, 0).await
+    }
+
+    async fn shifted_mock(data: Vec<u8>, shift: usize) -> (String, Mock) {
+        testutil::home();
+        let mock = Mock { data: Arc::new(data), ranges: Arc::default(), shift };
+        let router = Router::new().route("/{file}", get(serve_file)).with_state(mock.clone());
+        (testutil::spawn(router).await, mock)
+    }
+
+    fn random_data() -> Vec<u8> {
+        (0..4096).map(|_| rand::rng().random()).collect()
+    }
+
+    fn sha256_of(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    #[tokio::test]
+    async fn index_hash_mismatch_is_rejected_before_decryption() {
+        // bytes that could never decrypt, so only the hash check can produce the error
+        let (url, _) = mock(random_data()).await;
+        let expected = sha256_of(b"the package the index describes");
+        let loaded = LoadedRepo {
+            repo: Repository { name: "mock".into(), url: url.clone() },
+            source: RepoSource::Http(url),
+            index: Index::default(),
+        };
+        let entry = IndexEntry {
+            name: "mock".into(),
+            version: "1.0.0".into(),
+            file: "mock-1.0.0.pkg".into(),
+            sha256: expected.clone(),
+            size: 4096,
+            signer: None,
+            dependencies: Default::default(),
+            status: Default::default(),
+        };
+
+        let err = loaded.verified_path(&entry).await.unwrap_err().to_string();
+        assert!(err.contains("does not match the index hash"), "{err}");
+        let cache_dir = storage::get_cache_dir();
+        assert!(!cache_dir.join(format!("{expected}.pkg")).exists());
+        assert!(!cache_dir.join(format!("{expected}.pkg.part")).exists());
+    }
+
+    #[tokio::test]
+    async fn cache_hit_is_keyed_by_sha256() {
+        let data = random_data();
+        let (url, mock) = mock(data.clone()).await;
+        let sha256 = sha256_of(&data);
+
+        let first = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap();
+        assert_eq!(first, storage::get_cache_dir().join(format!("{sha256}.pkg")));
+        // another url with the same content is served from the cache
+        let second = fetch_cached(&format!("{url}/b.pkg"), &sha256).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(mock.ranges.lock().unwrap().len(), 1);
+        assert_eq!(std::fs::read(second).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn part_file_is_resumed_with_range() {
+        let data = random_data();
+        let (url, mock) = mock(data.clone()).await;
+        let sha256 = sha256_of(&data);
+        std::fs::create_dir_all(storage::get_cache_dir()).unwrap();
+        std::fs::write(storage::get_cache_dir().join(format!("{sha256}.pkg.part")), &data[..1000]).unwrap();
+
+        let path = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(*mock.ranges.lock().unwrap(), [Some("bytes=1000-".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn range_starting_elsewhere_restarts_the_download() {
+        let data = random_data();
+        let (url, mock) = shifted_mock(data.clone(), 500).await;
+        let sha256 = sha256_of(&data);
+        std::fs::create_dir_all(storage::get_cache_dir()).unwrap();
+        std::fs::write(storage::get_cache_dir().join(format!("{sha256}.pkg.part")), &data[..1000]).unwrap();
+
+        let path = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(*mock.ranges.lock().unwrap(), [Some("bytes=1000-".to_string()), None]);
+    }
+
+    #[tokio::test]
+    async fn complete_part_file_answered_416_is_kept() {
+        let data = random_data();
+        let (url, mock) = mock(data.clone()).await;
+        let sha256 = sha256_of(&data);
+        std::fs::create_dir_all(storage::get_cache_dir()).unwrap();
+        std::fs::write(storage::get_cache_dir().join(format!("{sha256}.pkg.part")), &data).unwrap();
+
+        let path = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(*mock.ranges.lock().unwrap(), [Some(format!("bytes={}-", data.len()))]);
+    }
+
+    #[tokio::test]
+    async fn corrupt_part_file_answered_416_is_dropped() {
+        let data = random_data();
+        let (url, _) = mock(data.clone()).await;
+        let sha256 = sha256_of(&data);
+        let part = storage::get_cache_dir().join(format!("{sha256}.pkg.part"));
+        std::fs::create_dir_all(storage::get_cache_dir()).unwrap();
+        std::fs::write(&part, random_data()).unwrap();
+
+        let err = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap_err().to_string();
+        assert!(err.contains("does not match the index hash"), "{err}");
+        assert!(!part.exists());
+    }
+
+    #[tokio::test]
+    async fn short_read_keeps_the_part_file_for_a_resume() {
+        testutil::home();
+        let data = random_data();
+        let sha256 = sha256_of(&data);
+
+        // announces the full length, sends half of it and hangs up
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (half, total) = (data[..2048].to_vec(), data.len());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // the whole request is read so closing does not reset the connection
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut buf = [0u8; 1024];
+                let n = tokio::io::AsyncReadExt::read(&mut socket, &mut buf).await.unwrap();
+                assert!(n > 0, "request ended early");
+                request.extend_from_slice(&buf[..n]);
+            }
+            let head = format!("HTTP/1.1 200 OK\r\ncontent-length: {total}\r\n\r\n");
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&half).await.unwrap();
+            socket.shutdown().await.unwrap();
+        });
+
+        assert!(fetch_cached(&format!("http://{addr}/a.pkg"), &sha256).await.is_err());
+        let part = storage::get_cache_dir().join(format!("{sha256}.pkg.part"));
+        assert_eq!(std::fs::read(&part).unwrap(), data[..2048]);
+
+        let (url, mock) = mock(data.clone()).await;
+        let path = fetch_cached(&format!("{url}/a.pkg"), &sha256).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(*mock.ranges.lock().unwrap(), [Some("bytes=2048-".to_string())]);
+    }
+}
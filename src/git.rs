@@ -0,0 +1,159 @@
+use std::{fs, path::PathBuf, process::Command};
+use rand::Rng;
+use semver::{BuildMetadata, Prerelease, Version};
+use crate::storage;
+
+// clean tree of a git repository at one commit, exported for build --git
+// only committed files are exported, so ignored and untracked files never reach the package
+pub struct GitCheckout {
+    // repository as given, local paths made absolute
+    pub repository: String,
+    pub commit: String,
+    // exported tree, the source dir of the build
+    pub dir: PathBuf,
+    // bare clone and tree, removed on drop
+    work_dir: PathBuf,
+}
+
+impl GitCheckout {
+    // clone a local path or url and export rev, HEAD by default
+    pub fn export(repository: &str, rev: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let repository = match fs::canonicalize(repository) {
+            Ok(path) => path.display().to_string(),
+            Err(_) => repository.to_string(),
+        };
+        let work_dir = storage::get_cache_dir().join(format!("git-{}", hex::encode(rand::rng().random::<[u8; 8]>())));
+        fs::create_dir_all(&work_dir)?;
+
+        // created before anything can fail so the work dir is always removed
+        let mut checkout = GitCheckout { repository, commit: String::new(), dir: work_dir.join("tree"), work_dir };
+        let bare = checkout.work_dir.join("repo.git");
+        run(Command::new("git").args(["clone", "--bare", "--quiet", "--", &checkout.repository]).arg(&bare))
+            .map_err(|e| format!("Cannot clone {}: {e}", checkout.repository))?;
+
+        let rev = rev.unwrap_or("HEAD");
+        checkout.commit = checkout.git(&["rev-parse", "--verify", "--quiet", &format!("{rev}^{{commit}}")])
+            .map_err(|_| format!("Unknown revision {rev} in {}", checkout.repository))?;
+
+        let tree = Command::new("git").arg("--git-dir").arg(&bare).args(["archive", "--format=tar", &checkout.commit]).output()?;
+        if !tree.status.success() {
+            return Err(format!("git archive failed: {}", String::from_utf8_lossy(&tree.stderr).trim()).into());
+        }
+        fs::create_dir_all(&checkout.dir)?;
+        tar::Archive::new(tree.stdout.as_slice()).unpack(&checkout.dir)?;
+        Ok(checkout)
+    }
+
+    pub fn short_commit(&self) -> &str {
+        &self.commit[..self.commit.len().min(12)]
+    }
+
+    // version of the nearest semver tag reachable from the commit, "v" prefixes are dropped
+    // commits after the tag get the next patch as a dev pre-release, e.g. 1.2.4-dev.3+g1a2b3c4d5e6f
+    pub fn version(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let mut nearest: Option<(u64, Version, String)> = None;
+        for tag in self.git(&["tag", "--merged", &self.commit])?.lines() {
+            let Ok(version) = Version::parse(tag.strip_prefix('v').unwrap_or(tag)) else { continue };
+            let distance: u64 = self.git(&["rev-list", "--count", &format!("{tag}..{}", self.commit)])?.parse()?;
+            // closest tag first, the highest version among tags of the same commit
+            let closer = nearest.as_ref().is_none_or(|(d, v, _)| distance < *d || (distance == *d && version > *v));
+            if closer {
+                nearest = Some((distance, version, tag.to_string()));
+            }
+        }
+
+        let Some((distance, mut version, tag)) = nearest else {
+            return Err(format!("No semver tag reachable from {}, pass a version", self.short_commit()).into());
+        };
+        if distance > 0 {
+            version.pre = if version.pre.is_empty() {
+                version.patch += 1;
+                Prerelease::new(&format!("dev.{distance}"))?
+            } else {
+                Prerelease::new(&format!("{}.dev.{distance}", version.pre))?
+            };
+            version.build = BuildMetadata::new(&format!("g{}", self.short_commit()))?;
+        }
+        println!("🏷️ Version {version} from tag {tag}{}", if distance > 0 { format!(" and {distance} commit(s) after it") } else { String::new() });
+        Ok(version.to_string())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+        run(Command::new("git").arg("--git-dir").arg(self.work_dir.join("repo.git")).args(args))
+    }
+}
+
+impl Drop for GitCheckout {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.work_dir);
+    }
+}
+
+// stdout of a git command, its stderr as the error
+fn run(command: &mut Command) -> Result<String, Box<dyn std::error::Error>> {
+    let out = command.output().map_err(|e| format!("Cannot run git: {e}"))?;
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string().into());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::testutil::{home, temp_dir};
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        run(Command::new("git").arg("-C").arg(repo).args(["-c", "user.name=test", "-c", "user.email=test@example.com"]).args(args)).unwrap()
+    }
+
+    fn commit(repo: &Path, file: &str) {
+        fs::write(repo.join(file), file).unwrap();
+        git(repo, &["add", file]);
+        git(repo, &["commit", "--quiet", "-m", file]);
+    }
+
+    fn version_at(repo: &Path, rev: &str) -> (String, String) {
+        let checkout = GitCheckout::export(&repo.display().to_string(), Some(rev)).unwrap();
+        (checkout.version().unwrap(), checkout.short_commit().to_string())
+    }
+
+    #[test]
+    fn version_comes_from_the_nearest_merged_tag() {
+        home();
+        let repo = temp_dir("git");
+        git(&repo, &["init", "--quiet", "-b", "main"]);
+        commit(&repo, "a");
+        git(&repo, &["tag", "v1.0.0"]);
+        git(&repo, &["tag", "1.1.0"]);
+        git(&repo, &["tag", "not-a-version"]);
+        assert_eq!(version_at(&repo, "main").0, "1.1.0");
+
+        // a higher tag on a branch that is not merged does not count
+        git(&repo, &["checkout", "--quiet", "-b", "side"]);
+        commit(&repo, "b");
+        git(&repo, &["tag", "v9.0.0"]);
+        git(&repo, &["checkout", "--quiet", "main"]);
+        commit(&repo, "c");
+        commit(&repo, "d");
+        let (version, sha) = version_at(&repo, "main");
+        assert_eq!(version, format!("1.1.1-dev.2+g{sha}"));
+        assert_eq!(version_at(&repo, "side").0, "9.0.0");
+
+        git(&repo, &["tag", "v2.0.0-rc.1"]);
+        commit(&repo, "e");
+        let (version, sha) = version_at(&repo, "main");
+        assert_eq!(version, format!("2.0.0-rc.1.dev.1+g{sha}"));
+    }
+
+    #[test]
+    fn repository_is_never_read_as_an_option() {
+        home();
+        let marker = temp_dir("git").join("pwned");
+        let repository = format!("--upload-pack=touch {}", marker.display());
+        let err = GitCheckout::export(&repository, None).err().unwrap().to_string();
+        assert!(err.contains(&format!("repository '{repository}' does not exist")), "{err}");
+        assert!(!marker.exists());
+    }
+}
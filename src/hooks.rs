@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, fmt, fs::{self, File, OpenOptions}, io::Write, path::{Path, PathBuf}, process::{ExitStatus, Stdio}, time::Duration};
+use rand::Rng;
+use serde::Deserialize;
+use tokio::process::{Child, Command};
+use crate::{manifest::SOURCE_MANIFEST, storage};
+
+// limit of one hook command in seconds
+pub const DEFAULT_TIMEOUT: u64 = 600;
+// variables kept from the caller environment, everything else is cleared
+const BASE_ENV: [&str; 6] = ["PATH", "HOME", "USER", "LANG", "TERM", "TMPDIR"];
+
+// [hooks] table of securepkg.toml, commands run with sh -c in the source dir
+#[derive(Deserialize, Debug, Default)]
+pub struct BuildHooks {
+    // before the files are hashed and packed, every file left in the source dir is packaged,
+    // so intermediate outputs belong in SECUREPKG_BUILD_DIR
+    #[serde(default)]
+    pub pre_build: Vec<String>,
+    // once the package is stored and registered
+    #[serde(default)]
+    pub post_build: Vec<String>,
+    pub timeout: Option<u64>,
+    // extra variables set for every command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // caller variables kept on top of the base ones, e.g. CARGO_HOME
+    #[serde(default)]
+    pub pass_env: Vec<String>,
+}
+
+// only the hooks of securepkg.toml, the rest is read by Manifest::load
+#[derive(Deserialize, Default)]
+struct SourceManifest {
+    #[serde(default)]
+    hooks: BuildHooks,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    PreBuild,
+    PostBuild,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::PreBuild => "pre-build",
+            Stage::PostBuild => "post-build",
+        })
+    }
+}
+
+impl BuildHooks {
+    pub fn load(src_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = src_dir.join(SOURCE_MANIFEST);
+        if !path.exists() {
+            return Ok(BuildHooks::default());
+        }
+        let source: SourceManifest = toml::from_str(&fs::read_to_string(&path)?).map_err(|e| format!("Invalid {SOURCE_MANIFEST}: {e}"))?;
+        Ok(source.hooks)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pre_build.is_empty() && self.post_build.is_empty()
+    }
+
+    fn commands(&self, stage: Stage) -> &[String] {
+        match stage {
+            Stage::PreBuild => &self.pre_build,
+            Stage::PostBuild => &self.post_build,
+        }
+    }
+}
+
+// hooks of one build, output of every command goes to ~/.securepkg/logs/<name>-<version>.build.log
+pub struct HookRunner {
+    hooks: BuildHooks,
+    src_dir: PathBuf,
+    vars: Vec<(String, String)>,
+    pub log_path: PathBuf,
+    // scratch dir for outputs that must stay out of the package, removed on drop
+    build_dir: PathBuf,
+}
+
+impl HookRunner {
+    pub fn new(hooks: BuildHooks, src_dir: &Path, name: &str, version: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(storage::get_logs_dir())?;
+        let log_path = storage::get_logs_dir().join(format!("{name}-{version}.build.log"));
+        File::create(&log_path)?; // every build starts a fresh log
+        let build_dir = storage::get_cache_dir().join(format!("build-{}", hex::encode(rand::rng().random::<[u8; 8]>())));
+        fs::create_dir_all(&build_dir)?;
+
+        let src_dir = fs::canonicalize(src_dir)?;
+        let vars = vec![
+            ("SECUREPKG_NAME".to_string(), name.to_string()),
+            ("SECUREPKG_VERSION".to_string(), version.to_string()),
+            ("SECUREPKG_SRC_DIR".to_string(), src_dir.display().to_string()),
+            ("SECUREPKG_BUILD_DIR".to_string(), build_dir.display().to_string()),
+        ];
+        Ok(HookRunner { hooks, src_dir, vars, log_path, build_dir })
+    }
+
+    // variable for the following stages, e.g. the package of post-build hooks
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.vars.push((key.to_string(), value.to_string()));
+    }
+
+    // run the commands of a stage in order, the first failure or timeout stops the build
+    pub async fn run(&self, stage: Stage) -> Result<(), Box<dyn std::error::Error>> {
+        let timeout = Duration::from_secs(self.hooks.timeout.unwrap_or(DEFAULT_TIMEOUT));
+        for command in self.hooks.commands(stage) {
+            println!("🪝 {stage}: {command}");
+            let mut log = OpenOptions::new().append(true).open(&self.log_path)?;
+            writeln!(log, "$ {command}   # {stage}")?;
+
+            let mut sh = Command::new("sh");
+            sh.arg("-c")
+                .arg(command)
+                .current_dir(&self.src_dir)
+                .env_clear()
+                .envs(self.environment())
+                .stdin(Stdio::null())
+                .stdout(log.try_clone()?)
+                .stderr(log)
+                .kill_on_drop(true);
+            // own process group so a timeout also stops what the shell started
+            #[cfg(unix)]
+            sh.process_group(0);
+            let mut child = sh.spawn().map_err(|e| format!("Cannot run {stage} hook `{command}`: {e}"))?;
+
+            match wait_or_kill(&mut child, timeout).await? {
+                None => return Err(self.failure(stage, command, &format!("timed out after {}s", timeout.as_secs()))),
+                Some(status) if !status.success() => return Err(self.failure(stage, command, &exit_reason(status))),
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // cargo output defaults to the build dir, a target/ in the source dir would be packaged
+    // then base variables, passed ones, [hooks.env] and the SECUREPKG_ ones, later entries win
+    fn environment(&self) -> Vec<(String, String)> {
+        let defaults = [("CARGO_TARGET_DIR".to_string(), self.build_dir.join("target").display().to_string())];
+        let passed = BASE_ENV.iter().map(|k| k.to_string())
+            .chain(self.hooks.pass_env.iter().cloned())
+            .filter_map(|k| std::env::var(&k).ok().map(|v| (k, v)));
+        defaults.into_iter()
+            .chain(passed)
+            .chain(self.hooks.env.clone())
+            .chain(self.vars.iter().cloned())
+            .collect()
+    }
+
+    // error ending with the last lines of the log
+    fn failure(&self, stage: Stage, command: &str, reason: &str) -> Box<dyn std::error::Error> {
+        format!("{stage} hook `{command}` {reason}, full log in {}:\n{}", self.log_path.display(), log_tail(&self.log_path)).into()
+    }
+}
+
+impl Drop for HookRunner {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.build_dir);
+    }
+}
+
+// exit status of a child started in its own process group, None when it timed out and the group was killed
+pub async fn wait_or_kill(child: &mut Child, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => Ok(Some(status?)),
+        Err(_) => {
+            #[cfg(unix)]
+            if let Some(pid) = child.id() {
+                unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+            }
+            let _ = child.kill().await;
+            Ok(None)
+        }
+    }
+}
+
+pub fn exit_reason(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with code {code}"),
+        None => "was killed by a signal".to_string(),
+    }
+}
+
+// last lines of a log file, shown when a command fails
+pub fn log_tail(path: &Path) -> String {
+    let log = fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = log.lines().collect();
+    lines[lines.len().saturating_sub(20)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    #[tokio::test]
+    async fn cargo_output_goes_to_the_build_dir() {
+        testutil::home();
+        let src = testutil::temp_dir("hooks");
+        let hooks = BuildHooks {
+            pre_build: vec!["mkdir -p \"$CARGO_TARGET_DIR\" && echo \"$CARGO_TARGET_DIR\" > target.txt".into(), "exit 3".into()],
+            ..Default::default()
+        };
+        let runner = HookRunner::new(hooks, &src, "hooks", "1.0.0").unwrap();
+        let err = runner.run(Stage::PreBuild).await.unwrap_err().to_string();
+        assert!(err.starts_with("pre-build hook `exit 3` exited with code 3"), "{err}");
+
+        let target = fs::read_to_string(src.join("target.txt")).unwrap();
+        assert!(Path::new(target.trim()).starts_with(&runner.build_dir), "{target}");
+        assert!(!src.join("target").exists());
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+use serde::Serialize;
+use crate::{manifest::{self, FileEntry, Manifest}, package::{locate_pkg, open_pkg}};
+
+// package contents and metadata, as printed by inspect
+#[derive(Serialize)]
+struct InspectReport {
+    path: String,
+    format: String,
+    manifest: Option<Manifest>,
+    files: Vec<FileEntry>,
+    total_size: u64,
+}
+
+// verify, decrypt in memory and print package contents without installing it
+pub async fn inspect_pkg(name: &str, version: &str, from_file: Option<&Path>, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let pkg_path = locate_pkg(name, version, from_file).await?;
+    let (format, entries) = open_pkg(name, &pkg_path)?;
+
+    let files = manifest::payload_files(&entries);
+    let report = InspectReport {
+        path: pkg_path.display().to_string(),
+        format: format.to_string(),
+        manifest: Manifest::from_entries(&entries)?,
+        total_size: files.iter().map(|f| f.size).sum(),
+        files,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("📦 Package: {}", report.path);
+    println!("🧾 Signature verified successfully");
+    println!("🗜️ Archive format: {}", report.format);
+
+    match &report.manifest {
+        Some(m) => {
+            println!("📄 Manifest:");
+            println!("  name: {}", m.name);
+            println!("  version: {}", m.version);
+            println!("  author: {}", m.author.as_deref().unwrap_or("unknown"));
+            if let Some(description) = &m.description {
+                println!("  description: {description}");
+            }
+            if let Some(license) = &m.license {
+                println!("  license: {license}");
+            }
+            if let Some(commit) = &m.commit {
+                println!("  commit: {commit}");
+            }
+            for (stage, script) in m.scripts.list() {
+                println!("  {stage} script: {script}");
+            }
+            for (dep, req) in &m.dependencies {
+                println!("  depends on: {dep} {req}");
+            }
+            if let Some(root) = &m.merkle_root {
+                println!("  merkle root: {root}");
+                match m.verify_files(&report.files) {
+                    Ok(_) => println!("🌳 All files match the signed manifest"),
+                    Err(e) => println!("⚠️ {e}"),
+                }
+            }
+        }
+        None => println!("⚠️ No embedded manifest (legacy package)"),
+    }
+
+    println!("📁 Files:");
+    for file in &report.files {
+        println!("  {:o} {:>10} {} {}", file.mode, file.size, file.sha256, file.path);
+    }
+    println!("📏 {} files, {} bytes uncompressed", report.files.len(), report.total_size);
+
+    Ok(())
+}
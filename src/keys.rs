@@ -0,0 +1,225 @@
+use std::{fs, path::{Path, PathBuf}};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, NaiveDate, SubsecRound, Utc};
+use rsa::{pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey, EncodeRsaPublicKey}, pkcs8::LineEnding, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey, traits::SignatureScheme};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use crate::{repo::IndexSignature, revocation::{self, RevocationReason}, storage, tuf::Signed};
+
+// public key and its expiry, signed by the key itself and handed to other publishers (key export)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyCert {
+    pub keyid: String,
+    pub public_key: String,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+// short id of a public key: first 16 hex chars of SHA-256 over its PKCS#1 DER
+pub fn key_id(key: &RsaPublicKey) -> Result<String, Box<dyn std::error::Error>> {
+    let der = key.to_pkcs1_der()?;
+    Ok(hex::encode(Sha256::digest(der.as_bytes()))[..16].to_string())
+}
+
+pub fn load_private_key() -> Result<RsaPrivateKey, Box<dyn std::error::Error>> {
+    let pem = fs::read_to_string(storage::get_keys_dir().join("private.pem"))?;
+    Ok(RsaPrivateKey::from_pkcs1_pem(&pem)?)
+}
+
+pub fn load_public_key() -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+    read_public_key(&storage::get_keys_dir().join("public.pem"))
+}
+
+pub fn read_public_key(path: &Path) -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("Cannot read public key {}: {e}", path.display()))?;
+    Ok(RsaPublicKey::from_pkcs1_pem(&pem)?)
+}
+
+// local private key, refused once it is revoked or expired
+pub fn signing_key() -> Result<RsaPrivateKey, Box<dyn std::error::Error>> {
+    let id = key_id(&load_public_key()?)?;
+    if let Some(problem) = key_problem(&id, Some(Utc::now()))? {
+        return Err(format!("Local key {id} is {problem}, generate a new keypair").into());
+    }
+    load_private_key()
+}
+
+// sign SHA-256 of data with the local private key
+pub fn sign_bytes(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    sign_with(&signing_key()?, data)
+}
+
+fn sign_unchecked(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    sign_with(&load_private_key()?, data)
+}
+
+pub fn sign_with(key: &RsaPrivateKey, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let digest = Sha256::digest(data);
+    Ok(key.sign(Pkcs1v15Sign::new::<Sha256>(), &digest)?)
+}
+
+pub fn verify_with(key: &RsaPublicKey, data: &[u8], signature: &[u8]) -> bool {
+    let digest = Sha256::digest(data);
+    Pkcs1v15Sign::new::<Sha256>().verify(key, &digest, signature).is_ok()
+}
+
+// data covered by a package signature made at signed_at
+pub fn timestamped(data: &[u8], signed_at: Option<DateTime<Utc>>) -> Vec<u8> {
+    match signed_at {
+        Some(at) => [data, b"\nsigned-at ", at.to_rfc3339().as_bytes()].concat(),
+        None => data.to_vec(), // legacy signature
+    }
+}
+
+// current time as recorded in signatures, without sub-seconds
+pub fn signing_time() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(0)
+}
+
+// trusted keys usable right now: not revoked, not expired
+pub fn trusted_keys() -> Result<Vec<(String, RsaPublicKey)>, Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    let mut usable = Vec::new();
+    for (id, key) in all_keys()? {
+        if key_problem(&id, Some(now))?.is_none() {
+            usable.push((id, key));
+        }
+    }
+    Ok(usable)
+}
+
+// local public key plus keys imported in keys/trusted, as (key id, key)
+pub fn all_keys() -> Result<Vec<(String, RsaPublicKey)>, Box<dyn std::error::Error>> {
+    let mut keys = Vec::new();
+
+    if let Ok(own) = load_public_key() {
+        keys.push((key_id(&own)?, own));
+    }
+
+    let trusted_dir = storage::get_trusted_dir();
+    if trusted_dir.exists() {
+        for entry in fs::read_dir(&trusted_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "pem") {
+                let key = read_public_key(&path)?;
+                keys.push((key_id(&key)?, key));
+            }
+        }
+    }
+    Ok(keys)
+}
+
+// why a key cannot back a signature judged at the given time (None when unknown), None when it can
+// callers pass a time the signer does not control, see translog::judged_at
+pub fn key_problem(id: &str, at: Option<DateTime<Utc>>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if let Some(revoked) = revocation::load(id)? {
+        // a compromised key may have signed anything, with any timestamp
+        if revoked.reason == RevocationReason::Compromised || at.is_none_or(|at| at >= revoked.revoked_at) {
+            return Ok(Some(format!("revoked ({}) since {}", revoked.reason, revoked.revoked_at)));
+        }
+    }
+    if let Some(expires) = load_cert(id)?.and_then(|cert| cert.expires)
+        && at.unwrap_or_else(Utc::now) > expires
+    {
+        return Ok(Some(format!("expired since {expires}")));
+    }
+    Ok(None)
+}
+
+// import a public key (PKCS#1 PEM) or a key certificate from key export into the trust store
+pub fn trust_key(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    fs::create_dir_all(storage::get_trusted_dir())?;
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let data = fs::read(path)?;
+        let signed: Signed<KeyCert> = serde_json::from_slice(&data).map_err(|e| format!("Invalid key certificate: {e}"))?;
+        let cert = &signed.signed;
+        verify_self_signed(&signed, &cert.public_key, &cert.keyid)?;
+        fs::write(storage::get_trusted_dir().join(format!("{}.pem", cert.keyid)), &cert.public_key)?;
+        fs::write(cert_path(&cert.keyid), data)?;
+        return Ok(cert.keyid.clone());
+    }
+
+    let key = read_public_key(path)?;
+    let id = key_id(&key)?;
+    fs::copy(path, storage::get_trusted_dir().join(format!("{id}.pem")))?;
+    Ok(id)
+}
+
+// certificate of the local key with an optional expiry, kept in keys/ and returned for distribution
+pub fn export_key(expires: Option<DateTime<Utc>>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let key = load_public_key()?;
+    let id = key_id(&key)?;
+    if let Some(problem) = key_problem(&id, Some(Utc::now()))? {
+        return Err(format!("Local key {id} is {problem}").into());
+    }
+    if expires.is_some_and(|at| at <= Utc::now()) {
+        return Err("Expiry must be in the future".into());
+    }
+
+    let cert = KeyCert {
+        keyid: id.clone(),
+        public_key: key.to_pkcs1_pem(LineEnding::LF)?,
+        created: signing_time(),
+        expires,
+    };
+    let data = serde_json::to_vec_pretty(&self_sign(cert)?)?;
+    fs::write(cert_path(&id), &data)?;
+    Ok(data)
+}
+
+// certificate of a key: keys/public.cert.json for the local key, keys/trusted/<id>.cert.json otherwise
+pub fn load_cert(id: &str) -> Result<Option<KeyCert>, Box<dyn std::error::Error>> {
+    let path = cert_path(id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let signed: Signed<KeyCert> = serde_json::from_slice(&fs::read(&path)?)?;
+    Ok(Some(signed.signed))
+}
+
+fn cert_path(id: &str) -> PathBuf {
+    let own = load_public_key().ok().and_then(|key| key_id(&key).ok());
+    if own.as_deref() == Some(id) {
+        storage::get_keys_dir().join("public.cert.json")
+    } else {
+        storage::get_trusted_dir().join(format!("{id}.cert.json"))
+    }
+}
+
+// sign with the local key even when it is revoked, used for certificates about the key itself
+pub fn self_sign<T: Serialize>(value: T) -> Result<Signed<T>, Box<dyn std::error::Error>> {
+    let payload = serde_json::to_vec(&value)?;
+    let signature = IndexSignature {
+        keyid: key_id(&load_public_key()?)?,
+        sig: general_purpose::STANDARD.encode(sign_unchecked(&payload)?),
+        signed_at: None,
+    };
+    Ok(Signed { signed: value, signatures: vec![signature] })
+}
+
+// check a self-signed file against the key it carries, returns that key
+pub fn verify_self_signed<T: Serialize + DeserializeOwned>(signed: &Signed<T>, pem: &str, keyid: &str) -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+    let key = RsaPublicKey::from_pkcs1_pem(pem)?;
+    if key_id(&key)? != keyid {
+        return Err(format!("Certificate key does not match key id {keyid}").into());
+    }
+    let payload = serde_json::to_vec(&signed.signed)?;
+    let valid = signed.signatures.iter().any(|s| {
+        s.keyid == keyid && verify_with(&key, &payload, &general_purpose::STANDARD.decode(&s.sig).unwrap_or_default())
+    });
+    if !valid {
+        return Err(format!("Certificate is not signed by key {keyid}").into());
+    }
+    Ok(key)
+}
+
+// date (YYYY-MM-DD, midnight UTC) or RFC 3339 time given on the command line
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("Invalid date {value}, expected YYYY-MM-DD or RFC 3339"))
+}
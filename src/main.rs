@@ -1,7 +1,38 @@
+mod advisory;
+mod archive;
+mod audit;
+mod auth;
+mod blob;
+mod check;
+mod config;
 mod cli;
+mod diff;
+mod fetch;
+mod git;
+mod hooks;
+mod inspect;
+mod keys;
+mod manifest;
+mod merkle;
 mod storage;
+mod translog;
+mod tuf;
 mod orm;
 mod package;
+mod policy;
+mod provenance;
+mod registry;
+mod repo;
+mod revocation;
+mod sbom;
+mod scripts;
+mod server;
+mod signatures;
+mod status;
+#[cfg(test)]
+mod testutil;
+mod upgrade;
+mod verify;
 #[macro_use]
 mod dsl;
 
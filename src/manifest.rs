@@ -0,0 +1,267 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{archive::{self, ArchiveEntry}, merkle, scripts::InstallScripts, storage};
+
+// manifest written by the package author in the source dir
+pub const SOURCE_MANIFEST: &str = "securepkg.toml";
+// reserved dir inside the archive for package metadata
+pub const META_DIR: &str = ".securepkg";
+pub const MANIFEST_ENTRY: &str = ".securepkg/manifest.json";
+
+// package manifest embedded in every archive
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    // per-file hashes sorted by path and their Merkle root, filled by build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    // git commit of builds with --git, set by build only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "InstallScripts::is_empty")]
+    pub scripts: InstallScripts,
+    // set by builds that record provenance, a package claiming it is refused without its .intoto
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provenance: bool,
+}
+
+// file path, size, mode and content hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    pub mode: u32,
+    pub sha256: String,
+}
+
+impl Manifest {
+    // read securepkg.toml from src_dir if present, CLI values take precedence
+    pub fn load(src_dir: &Path, name: &str, version: &str, author: Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = src_dir.join(SOURCE_MANIFEST);
+        let mut manifest = if path.exists() {
+            toml::from_str::<Manifest>(&fs::read_to_string(&path)?)
+                .map_err(|e| format!("Invalid {SOURCE_MANIFEST}: {e}"))?
+        } else {
+            Manifest::default()
+        };
+
+        manifest.name = name.to_string();
+        manifest.version = version.to_string();
+        manifest.commit = None;
+        if author.is_some() {
+            manifest.author = author;
+        }
+
+        // hash every payload file of the source dir
+        let files = hash_dir(src_dir)?;
+        for (stage, script) in manifest.scripts.list() {
+            if !files.iter().any(|f| f.path == script) {
+                return Err(format!("{stage} script {script} is not a file of the package").into());
+            }
+        }
+        manifest.merkle_root = Some(merkle_root(&files));
+        manifest.files = files;
+        Ok(manifest)
+    }
+
+    // recompute the Merkle root from the file list
+    pub fn verify_root(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.merkle_root {
+            Some(root) if *root == merkle_root(&self.files) => Ok(()),
+            Some(_) => Err("Manifest Merkle root does not match its file list".into()),
+            None => Err("Manifest has no Merkle root".into()),
+        }
+    }
+
+    // check actual files against the signed file list
+    pub fn verify_files(&self, actual: &[FileEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_root()?;
+        let changes = compare(&self.files, actual);
+        if changes.is_clean() {
+            Ok(())
+        } else {
+            Err(format!("File verification failed:\n{}", changes.describe()).into())
+        }
+    }
+
+    // verified manifest saved at install time, used to audit installed trees
+    pub fn installed_path(name: &str, version: &str) -> PathBuf {
+        storage::get_manifests_dir().join(format!("{}-{}.json", name, version))
+    }
+
+    pub fn save_installed(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Manifest::installed_path(&self.name, &self.version);
+        fs::create_dir_all(storage::get_manifests_dir())?;
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    // None for versions installed before manifests were recorded
+    pub fn load_installed(name: &str, version: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = Manifest::installed_path(name, version);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    // manifests of every installed package, sorted by name and version
+    pub fn list_installed() -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        let manifests_dir = storage::get_manifests_dir();
+        let mut manifests = Vec::new();
+
+        if manifests_dir.exists() {
+            for entry in fs::read_dir(&manifests_dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    manifests.push(serde_json::from_slice::<Manifest>(&fs::read(&path)?)?);
+                }
+            }
+        }
+        manifests.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(manifests)
+    }
+
+    // find the embedded manifest in archive entries, legacy packages have none
+    pub fn from_entries(entries: &[ArchiveEntry]) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match entries.iter().find(|e| e.path == MANIFEST_ENTRY) {
+            Some(entry) => Ok(Some(serde_json::from_slice(&entry.data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // manifest as an archive entry
+    pub fn to_entry(&self) -> Result<ArchiveEntry, Box<dyn std::error::Error>> {
+        Ok(ArchiveEntry {
+            path: MANIFEST_ENTRY.to_string(),
+            mode: 0o644,
+            is_dir: false,
+            data: serde_json::to_vec_pretty(self)?,
+        })
+    }
+}
+
+impl FileEntry {
+    pub fn from_archive(entry: &ArchiveEntry) -> Self {
+        FileEntry {
+            path: entry.path.clone(),
+            size: entry.data.len() as u64,
+            mode: entry.mode,
+            sha256: hex::encode(Sha256::digest(&entry.data)),
+        }
+    }
+
+    // Merkle leaf: path, mode, size and content hash
+    fn leaf(&self) -> merkle::Hash {
+        merkle::leaf_hash(format!("{}\0{:o}\0{}\0{}", self.path, self.mode, self.size, self.sha256).as_bytes())
+    }
+}
+
+// differences between an expected file list and actual files
+#[derive(Debug, Default, Serialize)]
+pub struct FileChanges {
+    pub modified: Vec<(String, String)>,
+    pub missing: Vec<String>,
+    pub unexpected: Vec<String>,
+}
+
+impl FileChanges {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        for (path, reason) in &self.modified {
+            lines.push(format!("  modified: {path} ({reason})"));
+        }
+        for path in &self.missing {
+            lines.push(format!("  missing: {path}"));
+        }
+        for path in &self.unexpected {
+            lines.push(format!("  unexpected: {path}"));
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn compare(expected: &[FileEntry], actual: &[FileEntry]) -> FileChanges {
+    let actual: BTreeMap<&str, &FileEntry> = actual.iter().map(|f| (f.path.as_str(), f)).collect();
+    let mut changes = FileChanges::default();
+
+    for file in expected {
+        match actual.get(file.path.as_str()) {
+            None => changes.missing.push(file.path.clone()),
+            Some(found) => {
+                let mut what = Vec::new();
+                if found.sha256 != file.sha256 {
+                    what.push("hash");
+                }
+                if found.size != file.size {
+                    what.push("size");
+                }
+                if found.mode != file.mode {
+                    what.push("mode");
+                }
+                if !what.is_empty() {
+                    changes.modified.push((file.path.clone(), what.join(", ")));
+                }
+            }
+        }
+    }
+
+    let known: Vec<&str> = expected.iter().map(|f| f.path.as_str()).collect();
+    changes.unexpected = actual.keys().filter(|p| !known.contains(p)).map(|p| p.to_string()).collect();
+    changes
+}
+
+// hex Merkle root over files sorted by path
+pub fn merkle_root(files: &[FileEntry]) -> String {
+    let mut sorted: Vec<&FileEntry> = files.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+    let leaves: Vec<merkle::Hash> = sorted.iter().map(|f| f.leaf()).collect();
+    hex::encode(merkle::root(&leaves))
+}
+
+// hash payload files found on disk, sorted by path
+pub fn hash_dir(dir: &Path) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for (entry, name) in archive::source_entries(dir)? {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let data = fs::read(entry.path())?;
+        files.push(FileEntry {
+            path: name.to_string_lossy().replace('\\', "/"),
+            size: data.len() as u64,
+            mode: archive::file_mode(entry.path()),
+            sha256: hex::encode(Sha256::digest(&data)),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+// true for entries inside the reserved metadata dir
+pub fn is_meta(path: &str) -> bool {
+    path == META_DIR || path.starts_with(&format!("{META_DIR}/"))
+}
+
+// payload files of a package, without dirs and metadata
+pub fn payload_files(entries: &[ArchiveEntry]) -> Vec<FileEntry> {
+    entries.iter()
+        .filter(|e| !e.is_dir && !is_meta(&e.path))
+        .map(FileEntry::from_archive)
+        .collect()
+}
@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// imported security advisory, affected holds semver ranges separated by ';'
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "advisories")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub advisory_id: String,
+    pub package: String,
+    pub affected: String,
+    pub severity: String,
+    pub description: String,
+    pub signer: String,
+    pub issued_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,39 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, IntoActiveModel, QueryOrder};
+use sea_orm::entity::prelude::*;
+use crate::orm::advisories::{ActiveModel, Column, Entity, Model};
+
+pub async fn find_advisory(conn: &DatabaseConnection, advisory_id: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::AdvisoryId.eq(advisory_id))
+        .one(conn)
+        .await
+}
+
+// create or replace an advisory by advisory_id (id is ignored), returns false when the stored one was issued later
+pub async fn save_advisory(conn: &DatabaseConnection, advisory: Model) -> Result<bool, DbErr> {
+    let mut row = match find_advisory(conn, &advisory.advisory_id).await? {
+        Some(row) if row.issued_at > advisory.issued_at => return Ok(false),
+        Some(row) => row.into_active_model(),
+        None => ActiveModel {
+            id: ActiveValue::NotSet,
+            advisory_id: ActiveValue::Set(advisory.advisory_id),
+            ..Default::default()
+        },
+    };
+    row.package = ActiveValue::Set(advisory.package);
+    row.affected = ActiveValue::Set(advisory.affected);
+    row.severity = ActiveValue::Set(advisory.severity);
+    row.description = ActiveValue::Set(advisory.description);
+    row.signer = ActiveValue::Set(advisory.signer);
+    row.issued_at = ActiveValue::Set(advisory.issued_at);
+    row.save(conn).await?;
+    Ok(true)
+}
+
+pub async fn list_advisories(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .order_by_asc(Column::Package)
+        .order_by_asc(Column::AdvisoryId)
+        .all(conn)
+        .await
+}
@@ -0,0 +1,96 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, EntityTrait, PaginatorTrait, QueryOrder, TransactionTrait};
+use sea_orm::entity::prelude::*;
+use crate::orm::{advisories, installed, models, signatures, sources, status, tokens, translog};
+
+// rows copied per table
+pub struct CopyStats {
+    pub packages: usize,
+    pub installed: usize,
+    pub tokens: usize,
+    pub signatures: usize,
+    pub log_entries: usize,
+    pub statuses: usize,
+    pub advisories: usize,
+    pub sources: usize,
+}
+
+// copy every row of from into an empty database, ids are assigned by the destination
+pub async fn copy_db(from: &DatabaseConnection, to: &DatabaseConnection) -> Result<CopyStats, DbErr> {
+    let existing = models::Entity::find().count(to).await?
+        + installed::Entity::find().count(to).await?
+        + tokens::Entity::find().count(to).await?
+        + signatures::Entity::find().count(to).await?
+        + translog::Entity::find().count(to).await?
+        + status::Entity::find().count(to).await?
+        + advisories::Entity::find().count(to).await?
+        + sources::Entity::find().count(to).await?;
+    if existing > 0 {
+        return Err(DbErr::Custom("Destination database is not empty".into()));
+    }
+
+    let packages = models::Entity::find().order_by_asc(models::Column::Id).all(from).await?;
+    let installed_rows = installed::Entity::find().order_by_asc(installed::Column::Id).all(from).await?;
+    let token_rows = tokens::Entity::find().order_by_asc(tokens::Column::Id).all(from).await?;
+    let signature_rows = signatures::Entity::find().order_by_asc(signatures::Column::Id).all(from).await?;
+    let log_rows = translog::Entity::find().order_by_asc(translog::Column::LeafIndex).all(from).await?;
+    let status_rows = status::Entity::find().order_by_asc(status::Column::Id).all(from).await?;
+    let advisory_rows = advisories::Entity::find().order_by_asc(advisories::Column::Id).all(from).await?;
+    let source_rows = sources::Entity::find().order_by_asc(sources::Column::Id).all(from).await?;
+    let stats = CopyStats {
+        packages: packages.len(),
+        installed: installed_rows.len(),
+        tokens: token_rows.len(),
+        signatures: signature_rows.len(),
+        log_entries: log_rows.len(),
+        statuses: status_rows.len(),
+        advisories: advisory_rows.len(),
+        sources: source_rows.len(),
+    };
+
+    // all or nothing
+    let txn = to.begin().await?;
+    for row in packages {
+        let mut row: models::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in installed_rows {
+        let mut row: installed::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in token_rows {
+        let mut row: tokens::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in signature_rows {
+        let mut row: signatures::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    // leaf indexes are kept, they are part of the log
+    for row in log_rows {
+        let mut row: translog::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in status_rows {
+        let mut row: status::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in advisory_rows {
+        let mut row: advisories::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    for row in source_rows {
+        let mut row: sources::ActiveModel = row.into();
+        row.id = ActiveValue::NotSet;
+        row.reset_all().insert(&txn).await?;
+    }
+    txn.commit().await?;
+
+    Ok(stats)
+}
@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// installed version of a package
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "installed")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+    pub active: bool,
+    pub installed_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
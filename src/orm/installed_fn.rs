@@ -0,0 +1,78 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, QueryOrder, TransactionTrait};
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::Expr;
+use chrono::Utc;
+use crate::orm::installed::{ActiveModel, Column, Entity, Model};
+
+// record an installed version, reinstalling keeps its active flag
+pub async fn record_install(conn: &DatabaseConnection, name: &str, version: &str) -> Result<(), DbErr> {
+    if find_install(conn, name, version).await?.is_some() {
+        return Ok(());
+    }
+
+    let installed = ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name.to_string()),
+        version: ActiveValue::Set(version.to_string()),
+        active: ActiveValue::Set(false),
+        installed_at: ActiveValue::Set(Utc::now()),
+    };
+    installed.insert(conn).await?;
+    Ok(())
+}
+
+pub async fn find_install(conn: &DatabaseConnection, name: &str, version: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .one(conn)
+        .await
+}
+
+pub async fn find_active(conn: &DatabaseConnection, name: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Active.eq(true))
+        .one(conn)
+        .await
+}
+
+// mark one version active and every other version of the package inactive
+pub async fn set_active(conn: &DatabaseConnection, name: &str, version: &str) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+
+    Entity::update_many()
+        .col_expr(Column::Active, Expr::value(false))
+        .filter(Column::Name.eq(name))
+        .exec(&txn)
+        .await?;
+
+    let result = Entity::update_many()
+        .col_expr(Column::Active, Expr::value(true))
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .exec(&txn)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(DbErr::Custom(format!("❌ {name} {version} is not installed")));
+    }
+    txn.commit().await
+}
+
+pub async fn remove_install(conn: &DatabaseConnection, name: &str, version: &str) -> Result<(), DbErr> {
+    Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn list_installed(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .order_by_asc(Column::Name)
+        .order_by_asc(Column::InstalledAt)
+        .all(conn)
+        .await
+}
@@ -1,21 +1,51 @@
-use sea_query::SqliteQueryBuilder;
-use sea_orm::{Database, DatabaseConnection, DbBackend, Statement, ConnectionTrait};
-use sea_query::{Table, ColumnDef, Expr, Iden};
-use crate::storage;
+use sea_orm::{Database, DatabaseConnection, ConnectionTrait};
+use sea_query::{Table, TableCreateStatement, ColumnDef, Expr, Iden};
+use crate::{config::Config, storage};
+pub mod advisories;
+pub mod advisories_fn;
+pub mod copy_fn;
+pub mod installed;
+pub mod installed_fn;
 pub mod models;
 pub mod publish_fn;
+pub mod schema_fn;
+pub mod signatures;
+pub mod signatures_fn;
+pub mod sources;
+pub mod sources_fn;
+pub mod status;
+pub mod status_fn;
+pub mod tokens;
+pub mod tokens_fn;
+pub mod translog;
+pub mod translog_fn;
 
-// connect to database
+// connect to the configured database, the local SQLite file by default
 pub async fn connectdb() -> Result<DatabaseConnection, sea_orm::DbErr> {
-    let sqlite_db = storage::get_db_path();
-    let db_url = format!("sqlite://{}", sqlite_db.to_string_lossy());
-    let conn = Database::connect(&db_url).await?;
+    connect(&database_url()).await
+}
+
+// connect to any supported url (sqlite://, postgres://) and bring its schema up to date
+pub async fn connect(db_url: &str) -> Result<DatabaseConnection, sea_orm::DbErr> {
+    let conn = Database::connect(db_url).await?;
+    schema_fn::migrate(&conn).await?;
     Ok(conn)
 }
 
+// SECUREPKG_DATABASE_URL, then [database] url of config.toml, then ~/.securepkg/db.sqlite
+pub fn database_url() -> String {
+    std::env::var("SECUREPKG_DATABASE_URL").ok()
+        .or_else(|| Config::load().ok().and_then(|config| config.database.url))
+        .unwrap_or_else(sqlite_url)
+}
+
+pub fn sqlite_url() -> String {
+    format!("sqlite://{}", storage::get_db_path().to_string_lossy())
+}
+
 // pkgs columns
 #[derive(Iden)]
-enum Packages {
+pub enum Packages {
     Table,
     Id,
     Name,
@@ -27,9 +57,102 @@ enum Packages {
     CreatedAt,
 }
 
-pub async fn create_table(conn: &DatabaseConnection) -> Result<(), sea_orm::DbErr> {
-    // create table
-    let table = Table::create()
+// installed versions columns
+#[derive(Iden)]
+enum InstalledVersions {
+    #[iden = "installed"]
+    Table,
+    Id,
+    Name,
+    Version,
+    Active,
+    InstalledAt,
+}
+
+// package signatures columns
+#[derive(Iden)]
+enum Signatures {
+    Table,
+    Id,
+    Name,
+    Version,
+    Keyid,
+    Signature,
+    CreatedAt,
+}
+
+// yank and deprecation columns
+#[derive(Iden)]
+enum PackageStatus {
+    Table,
+    Id,
+    Name,
+    Version,
+    Yanked,
+    Deprecated,
+    UpdatedAt,
+}
+
+// transparency log columns
+#[derive(Iden)]
+enum TransparencyLog {
+    Table,
+    Id,
+    LeafIndex,
+    Name,
+    Version,
+    Sha256,
+    Signers,
+    LoggedAt,
+}
+
+// security advisories columns
+#[derive(Iden)]
+enum Advisories {
+    Table,
+    Id,
+    AdvisoryId,
+    Package,
+    Affected,
+    Severity,
+    Description,
+    Signer,
+    IssuedAt,
+}
+
+// git sources columns
+#[derive(Iden)]
+enum PackageSources {
+    Table,
+    Id,
+    Name,
+    Version,
+    Repository,
+    Commit,
+    CreatedAt,
+}
+
+// registry api tokens columns
+#[derive(Iden)]
+enum ApiTokens {
+    Table,
+    Id,
+    Name,
+    TokenHash,
+    Scopes,
+    CreatedAt,
+}
+
+// run a CREATE TABLE built for the backend of conn
+pub async fn execute<C: ConnectionTrait>(conn: &C, table: &TableCreateStatement) -> Result<(), sea_orm::DbErr> {
+    let backend = conn.get_database_backend();
+    conn.execute(backend.build(table)).await?;
+    Ok(())
+}
+
+// packages table, also rebuilt by the schema migration of created_at
+pub fn packages_table() -> TableCreateStatement {
+    Table::create()
         .table(Packages::Table)
         .if_not_exists()
         .col(ColumnDef::new(Packages::Id).integer().not_null().auto_increment().primary_key())
@@ -39,10 +162,104 @@ pub async fn create_table(conn: &DatabaseConnection) -> Result<(), sea_orm::DbEr
         .col(ColumnDef::new(Packages::Hash).string())
         .col(ColumnDef::new(Packages::Signature).string())
         .col(ColumnDef::new(Packages::EncryptedPath).string())
-        .col(ColumnDef::new(Packages::CreatedAt).date_time().default(Expr::current_timestamp()))
+        .col(ColumnDef::new(Packages::CreatedAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned()
+}
+
+// every table of schema version 1, missing ones are created
+pub async fn create_table<C: ConnectionTrait>(conn: &C) -> Result<(), sea_orm::DbErr> {
+    execute(conn, &packages_table()).await?;
+
+    // installed versions, one active per name
+    let table = Table::create()
+        .table(InstalledVersions::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(InstalledVersions::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(InstalledVersions::Name).string().not_null())
+        .col(ColumnDef::new(InstalledVersions::Version).string().not_null())
+        .col(ColumnDef::new(InstalledVersions::Active).boolean().not_null().default(false))
+        .col(ColumnDef::new(InstalledVersions::InstalledAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned();
+    execute(conn, &table).await?;
+
+    // api tokens of the registry server, only SHA-256 hashes are stored
+    let table = Table::create()
+        .table(ApiTokens::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(ApiTokens::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(ApiTokens::Name).string().not_null().unique_key())
+        .col(ColumnDef::new(ApiTokens::TokenHash).string().not_null().unique_key())
+        .col(ColumnDef::new(ApiTokens::Scopes).string().not_null())
+        .col(ColumnDef::new(ApiTokens::CreatedAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned();
+    execute(conn, &table).await?;
+
+    // every signature of a package, co-signers add rows
+    let table = Table::create()
+        .table(Signatures::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(Signatures::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(Signatures::Name).string().not_null())
+        .col(ColumnDef::new(Signatures::Version).string().not_null())
+        .col(ColumnDef::new(Signatures::Keyid).string().not_null())
+        .col(ColumnDef::new(Signatures::Signature).text().not_null())
+        .col(ColumnDef::new(Signatures::CreatedAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned();
+    execute(conn, &table).await?;
+
+    // yanked and deprecated versions, one row per version
+    let table = Table::create()
+        .table(PackageStatus::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(PackageStatus::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(PackageStatus::Name).string().not_null())
+        .col(ColumnDef::new(PackageStatus::Version).string().not_null())
+        .col(ColumnDef::new(PackageStatus::Yanked).string())
+        .col(ColumnDef::new(PackageStatus::Deprecated).string())
+        .col(ColumnDef::new(PackageStatus::UpdatedAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned();
+    execute(conn, &table).await?;
+
+    // append-only log of publishes, leaves in leaf_index order
+    let table = Table::create()
+        .table(TransparencyLog::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(TransparencyLog::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(TransparencyLog::LeafIndex).big_integer().not_null().unique_key())
+        .col(ColumnDef::new(TransparencyLog::Name).string().not_null())
+        .col(ColumnDef::new(TransparencyLog::Version).string().not_null())
+        .col(ColumnDef::new(TransparencyLog::Sha256).string().not_null())
+        .col(ColumnDef::new(TransparencyLog::Signers).string().not_null())
+        .col(ColumnDef::new(TransparencyLog::LoggedAt).timestamp_with_time_zone().not_null())
         .to_owned();
+    execute(conn, &table).await?;
 
-    let sql = table.to_string(SqliteQueryBuilder); // convert statement to SQL
-    conn.execute(Statement::from_string(DbBackend::Sqlite, sql)).await?; // execute query
+    // advisories imported from signed bundles, one row per advisory id
+    let table = Table::create()
+        .table(Advisories::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(Advisories::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(Advisories::AdvisoryId).string().not_null().unique_key())
+        .col(ColumnDef::new(Advisories::Package).string().not_null())
+        .col(ColumnDef::new(Advisories::Affected).text().not_null())
+        .col(ColumnDef::new(Advisories::Severity).string().not_null())
+        .col(ColumnDef::new(Advisories::Description).text().not_null())
+        .col(ColumnDef::new(Advisories::Signer).string().not_null())
+        .col(ColumnDef::new(Advisories::IssuedAt).timestamp_with_time_zone().not_null())
+        .to_owned();
+    execute(conn, &table).await?;
+
+    // repository and commit of versions built with --git, one row per version
+    let table = Table::create()
+        .table(PackageSources::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(PackageSources::Id).integer().not_null().auto_increment().primary_key())
+        .col(ColumnDef::new(PackageSources::Name).string().not_null())
+        .col(ColumnDef::new(PackageSources::Version).string().not_null())
+        .col(ColumnDef::new(PackageSources::Repository).text().not_null())
+        .col(ColumnDef::new(PackageSources::Commit).string().not_null())
+        .col(ColumnDef::new(PackageSources::CreatedAt).timestamp_with_time_zone().default(Expr::current_timestamp()))
+        .to_owned();
+    execute(conn, &table).await?;
     Ok(())
-}
\ No newline at end of file
+}
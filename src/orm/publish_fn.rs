@@ -52,4 +52,11 @@ pub async fn update_signature(db: &DatabaseConnection, name: &str, version: &str
 pub async fn list_pkg(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
     let packages = Entity::find().all(conn).await?;
     Ok(packages)
-}
\ No newline at end of file
+}
+// every version registered for a package name
+pub async fn find_versions(conn: &DatabaseConnection, name: &str) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::Name.eq(name))
+        .all(conn)
+        .await
+}
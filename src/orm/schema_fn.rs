@@ -0,0 +1,128 @@
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, Statement, TransactionTrait};
+use sea_query::{Alias, ColumnDef, Iden, Query, Table};
+use crate::orm::{create_table, execute, packages_table, Packages};
+
+// schema written by this build, every step of migrate_step brings a database one version further
+pub const SCHEMA_VERSION: i32 = 2;
+
+// single row holding the schema version of the database
+#[derive(Iden)]
+enum SchemaVersion {
+    Table,
+    Version,
+}
+
+// upgrade the schema step by step, each step and its version bump in one transaction
+// databases from before versioning count as version 0
+pub async fn migrate(conn: &DatabaseConnection) -> Result<(), DbErr> {
+    let table = Table::create()
+        .table(SchemaVersion::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(SchemaVersion::Version).integer().not_null())
+        .to_owned();
+    execute(conn, &table).await?;
+
+    let current = version(conn).await?;
+    if current > SCHEMA_VERSION {
+        return Err(DbErr::Custom(format!("Database schema version {current} is newer than this securepkg supports ({SCHEMA_VERSION}), upgrade securepkg")));
+    }
+    for step in current + 1..=SCHEMA_VERSION {
+        let txn = conn.begin().await?;
+        migrate_step(&txn, step).await?;
+        set_version(&txn, step).await?;
+        txn.commit().await?;
+    }
+    Ok(())
+}
+
+pub async fn version<C: ConnectionTrait>(conn: &C) -> Result<i32, DbErr> {
+    let query = Query::select().column(SchemaVersion::Version).from(SchemaVersion::Table).to_owned();
+    let row = conn.query_one(conn.get_database_backend().build(&query)).await?;
+    Ok(match row {
+        Some(row) => row.try_get("", &SchemaVersion::Version.to_string())?,
+        None => 0,
+    })
+}
+
+async fn set_version<C: ConnectionTrait>(conn: &C, version: i32) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    conn.execute(backend.build(&Query::delete().from_table(SchemaVersion::Table).to_owned())).await?;
+    let insert = Query::insert().into_table(SchemaVersion::Table).columns([SchemaVersion::Version]).values_panic([version.into()]).to_owned();
+    conn.execute(backend.build(&insert)).await?;
+    Ok(())
+}
+
+async fn migrate_step<C: ConnectionTrait>(conn: &C, step: i32) -> Result<(), DbErr> {
+    match step {
+        // every table, missing ones are added to databases created by older builds
+        1 => create_table(conn).await,
+        // the first SQLite schema declared packages.created_at without a time zone
+        // and CURRENT_TIMESTAMP defaults left naive values, rebuild the column as UTC
+        2 if conn.get_database_backend() == DbBackend::Sqlite => {
+            let mut table = packages_table();
+            table.table(Alias::new("packages_new"));
+            conn.execute_unprepared("DROP TABLE IF EXISTS packages_new").await?;
+            execute(conn, &table).await?;
+
+            let columns = [Packages::Id, Packages::Name, Packages::Version, Packages::Author, Packages::Hash, Packages::Signature, Packages::EncryptedPath]
+                .map(|c| format!("\"{}\"", c.to_string()))
+                .join(", ");
+            let created_at = Packages::CreatedAt.to_string();
+            conn.execute(Statement::from_string(DbBackend::Sqlite, format!(
+                "INSERT INTO packages_new ({columns}, \"{created_at}\") SELECT {columns}, \
+                 CASE WHEN instr(\"{created_at}\", 'T') = 0 THEN replace(\"{created_at}\", ' ', 'T') || '+00:00' ELSE \"{created_at}\" END FROM packages"
+            ))).await?;
+            conn.execute_unprepared("DROP TABLE packages").await?;
+            conn.execute_unprepared("ALTER TABLE packages_new RENAME TO packages").await?;
+            Ok(())
+        }
+        2 => Ok(()),
+        _ => Err(DbErr::Custom(format!("No schema migration to version {step}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::{orm::{self, publish_fn}, testutil};
+
+    #[tokio::test]
+    async fn upgrades_a_database_of_the_first_schema() {
+        let url = format!("sqlite://{}?mode=rwc", testutil::temp_dir("schema").join("db.sqlite").display());
+        let old = sea_orm::Database::connect(&url).await.unwrap();
+        old.execute_unprepared(
+            "CREATE TABLE packages (id integer NOT NULL PRIMARY KEY AUTOINCREMENT, name varchar NOT NULL, version varchar NOT NULL, \
+             author varchar, hash varchar, signature varchar, encrypted_path varchar, created_at datetime_text DEFAULT CURRENT_TIMESTAMP); \
+             INSERT INTO packages (name, version, created_at) VALUES ('a', '1.0.0', '2025-03-01T10:00:00+00:00'); \
+             INSERT INTO packages (name, version, created_at) VALUES ('b', '1.0.0', '2025-03-02 11:30:00');",
+        ).await.unwrap();
+        old.close().await.unwrap();
+
+        let conn = orm::connect(&url).await.unwrap();
+        assert_eq!(version(&conn).await.unwrap(), SCHEMA_VERSION);
+        let pkgs = publish_fn::list_pkg(&conn).await.unwrap();
+        assert_eq!(pkgs.len(), 2);
+        assert_eq!(pkgs[1].created_at, Utc.with_ymd_and_hms(2025, 3, 2, 11, 30, 0).unwrap());
+        // tables added after the first schema exist and the next id follows the copied rows
+        assert!(orm::status_fn::list_status(&conn).await.unwrap().is_empty());
+        publish_fn::insert_package(&conn, "c".into(), "1.0.0".into(), None, None, None).await.unwrap();
+        assert_eq!(publish_fn::find_pkg(&conn, "c", "1.0.0").await.unwrap().unwrap().id, 3);
+
+        // connecting again changes nothing
+        drop(conn);
+        let conn = orm::connect(&url).await.unwrap();
+        assert_eq!(publish_fn::list_pkg(&conn).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn refuses_a_newer_schema() {
+        let url = format!("sqlite://{}?mode=rwc", testutil::temp_dir("schema").join("db.sqlite").display());
+        let conn = orm::connect(&url).await.unwrap();
+        set_version(&conn, SCHEMA_VERSION + 1).await.unwrap();
+        drop(conn);
+
+        let err = orm::connect(&url).await.unwrap_err().to_string();
+        assert!(err.contains("newer than this securepkg supports"), "{err}");
+    }
+}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// one signature of a package, co-signed packages have several rows
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "signatures")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+    pub keyid: String,
+    pub signature: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,28 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, TransactionTrait};
+use sea_orm::entity::prelude::*;
+use chrono::Utc;
+use crate::orm::signatures::{ActiveModel, Column, Entity};
+use crate::repo::IndexSignature;
+
+// replace the recorded signatures of a package with the ones of its .sig
+pub async fn replace_signatures(conn: &DatabaseConnection, name: &str, version: &str, signatures: &[IndexSignature]) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .exec(&txn)
+        .await?;
+
+    for s in signatures {
+        let row = ActiveModel {
+            id: ActiveValue::NotSet,
+            name: ActiveValue::Set(name.to_string()),
+            version: ActiveValue::Set(version.to_string()),
+            keyid: ActiveValue::Set(s.keyid.clone()),
+            signature: ActiveValue::Set(s.sig.clone()),
+            created_at: ActiveValue::Set(Utc::now()),
+        };
+        row.insert(&txn).await?;
+    }
+    txn.commit().await
+}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// git repository and commit a version was built from
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "package_sources")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+    pub repository: String,
+    pub commit: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
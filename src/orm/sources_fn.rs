@@ -0,0 +1,47 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, IntoActiveModel};
+use sea_orm::entity::prelude::*;
+use chrono::Utc;
+use crate::orm::sources::{ActiveModel, Column, Entity, Model};
+
+pub async fn find_source(conn: &DatabaseConnection, name: &str, version: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .one(conn)
+        .await
+}
+
+// create or replace the source of a version, rebuilds record their new commit
+pub async fn save_source(conn: &DatabaseConnection, name: &str, version: &str, repository: &str, commit: &str) -> Result<(), DbErr> {
+    match find_source(conn, name, version).await? {
+        Some(row) => {
+            let mut row = row.into_active_model();
+            row.repository = ActiveValue::Set(repository.to_string());
+            row.commit = ActiveValue::Set(commit.to_string());
+            row.created_at = ActiveValue::Set(Utc::now());
+            row.update(conn).await?;
+        }
+        None => {
+            let row = ActiveModel {
+                id: ActiveValue::NotSet,
+                name: ActiveValue::Set(name.to_string()),
+                version: ActiveValue::Set(version.to_string()),
+                repository: ActiveValue::Set(repository.to_string()),
+                commit: ActiveValue::Set(commit.to_string()),
+                created_at: ActiveValue::Set(Utc::now()),
+            };
+            row.insert(conn).await?;
+        }
+    }
+    Ok(())
+}
+
+// sources are only recorded for git builds, plain dir builds keep none
+pub async fn remove_source(conn: &DatabaseConnection, name: &str, version: &str) -> Result<(), DbErr> {
+    Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// yank and deprecation flags of a published version, reasons may be empty
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "package_status")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+    pub yanked: Option<String>,
+    pub deprecated: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
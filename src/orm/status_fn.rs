@@ -0,0 +1,41 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, IntoActiveModel};
+use sea_orm::entity::prelude::*;
+use chrono::Utc;
+use crate::orm::status::{ActiveModel, Column, Entity, Model};
+
+pub async fn find_status(conn: &DatabaseConnection, name: &str, version: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::Name.eq(name))
+        .filter(Column::Version.eq(version))
+        .one(conn)
+        .await
+}
+
+// create or replace the flags of a version
+pub async fn save_status(conn: &DatabaseConnection, name: &str, version: &str, yanked: Option<String>, deprecated: Option<String>) -> Result<(), DbErr> {
+    match find_status(conn, name, version).await? {
+        Some(row) => {
+            let mut row = row.into_active_model();
+            row.yanked = ActiveValue::Set(yanked);
+            row.deprecated = ActiveValue::Set(deprecated);
+            row.updated_at = ActiveValue::Set(Utc::now());
+            row.update(conn).await?;
+        }
+        None => {
+            let row = ActiveModel {
+                id: ActiveValue::NotSet,
+                name: ActiveValue::Set(name.to_string()),
+                version: ActiveValue::Set(version.to_string()),
+                yanked: ActiveValue::Set(yanked),
+                deprecated: ActiveValue::Set(deprecated),
+                updated_at: ActiveValue::Set(Utc::now()),
+            };
+            row.insert(conn).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_status(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find().all(conn).await
+}
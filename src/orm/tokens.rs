@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// registry api token, scopes are comma separated
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "api_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,35 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, QueryOrder};
+use sea_orm::entity::prelude::*;
+use chrono::Utc;
+use crate::orm::tokens::{ActiveModel, Column, Entity, Model};
+
+pub async fn insert_token(conn: &DatabaseConnection, name: &str, token_hash: String, scopes: String) -> Result<(), DbErr> {
+    let token = ActiveModel {
+        id: ActiveValue::NotSet,
+        name: ActiveValue::Set(name.to_string()),
+        token_hash: ActiveValue::Set(token_hash),
+        scopes: ActiveValue::Set(scopes),
+        created_at: ActiveValue::Set(Utc::now()),
+    };
+    token.insert(conn).await?;
+    Ok(())
+}
+
+pub async fn find_by_hash(conn: &DatabaseConnection, token_hash: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::TokenHash.eq(token_hash))
+        .one(conn)
+        .await
+}
+
+pub async fn delete_token(conn: &DatabaseConnection, name: &str) -> Result<u64, DbErr> {
+    let result = Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .exec(conn)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+pub async fn list_tokens(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find().order_by_asc(Column::Name).all(conn).await
+}
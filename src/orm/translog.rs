@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+
+// one publish of the append-only transparency log, rows are never updated or deleted
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "transparency_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub leaf_index: i64,
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub signers: String,
+    pub logged_at: DateTime<Utc>,
+}
+
+// active model
+#[derive(Debug, Clone, Copy, EnumIter, DeriveRelation)] pub enum Relation {}
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,29 @@
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, PaginatorTrait, QueryOrder, TransactionTrait};
+use sea_orm::entity::prelude::*;
+use chrono::{DateTime, Utc};
+use crate::orm::translog::{ActiveModel, Column, Entity, Model};
+
+// append a leaf at the end of the log, leaf_index is unique so concurrent appends cannot share one
+pub async fn append_entry(conn: &DatabaseConnection, name: &str, version: &str, sha256: &str, signers: &[String], logged_at: DateTime<Utc>) -> Result<Model, DbErr> {
+    let txn = conn.begin().await?;
+    let leaf_index = Entity::find().count(&txn).await? as i64;
+    let row = ActiveModel {
+        id: ActiveValue::NotSet,
+        leaf_index: ActiveValue::Set(leaf_index),
+        name: ActiveValue::Set(name.to_string()),
+        version: ActiveValue::Set(version.to_string()),
+        sha256: ActiveValue::Set(sha256.to_string()),
+        signers: ActiveValue::Set(signers.join(",")),
+        logged_at: ActiveValue::Set(logged_at),
+    };
+    let row = row.insert(&txn).await?;
+    txn.commit().await?;
+    Ok(row)
+}
+
+pub async fn list_entries(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .order_by_asc(Column::LeafIndex)
+        .all(conn)
+        .await
+}
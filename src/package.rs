@@ -1,55 +1,42 @@
-use std::{fs::{self, File}, path::{Path, PathBuf}};
-use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce, aead::{Aead, OsRng, rand_core::RngCore}};
-use zip::{write::FileOptions, CompressionMethod};
-use walkdir::WalkDir;
-use rsa::{pkcs1::DecodeRsaPrivateKey,pkcs1::DecodeRsaPublicKey, RsaPrivateKey, Pkcs1v15Sign, RsaPublicKey, traits::SignatureScheme};
-use sha2::{Digest, Sha256};
-use crate::{orm, storage};
+use std::{fs, path::{Path, PathBuf}};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce, aead::{Aead, OsRng, Payload, rand_core::RngCore}};
+use crate::{archive::{self, ArchiveEntry, ArchiveFormat}, blob, git::GitCheckout, hooks::{BuildHooks, HookRunner, Stage}, keys, manifest::{hash_dir, is_meta, payload_files, Manifest}, orm, policy, provenance::{self, PROVENANCE_EXTENSION}, repo, sbom, scripts::{self, ScriptStage}, signatures::{self, SignatureSet}, status, storage, translog::{self, LOG_FILE, PROOF_EXTENSION}};
 use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
 
-// to compress dirs in .zip
-pub fn zip_dir(src_dir: &Path, dst_file: &Path) -> zip::result::ZipResult<()> {
-    let file = File::create(dst_file)?; // Create file
-    let mut zip = zip::ZipWriter::new(file); // zip writer
-
-    // file options
-    let options = FileOptions::default()
-        .compression_method(CompressionMethod::Deflated)
-        .unix_permissions(0o755);
-
-    // travel dirs and subdirs in src_dir
-    for entry in WalkDir::new(src_dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let name = path.strip_prefix(src_dir).unwrap(); // keep path structure and use a relative way
-
-        if path.is_file() { // if is file:
-            zip.start_file(name.to_string_lossy(), options)?; // create file
-            let mut f = File::open(path)?;
-            std::io::copy(&mut f, &mut zip)?; // copy its content in zip archive
-        } else if name.as_os_str().len() != 0 { // if is folder:
-            zip.add_directory(name.to_string_lossy(), options)?; // add a dir to zip
-        }
-        zip.finish()?; // close zip
-    }
-    Ok(())
+// .pkg container header: magic, container version and archive format
+const PKG_MAGIC: &[u8; 4] = b"SPKG";
+const PKG_VERSION: u8 = 1;
+const HEADER_LEN: usize = 6;
+const NONCE_LEN: usize = 12;
+
+fn pkg_header(format: ArchiveFormat) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(PKG_MAGIC);
+    header[4] = PKG_VERSION;
+    header[5] = format.id();
+    header
 }
 
-// to encrypt zip archive in pkg
-pub fn encrypt_zip(input: &Path, output: &Path, key: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let read_zip = fs::read(input)?; // read input path (zip)
+// to encrypt an archive in pkg, the header is authenticated as associated data
+pub fn encrypt_archive(input: &Path, output: &Path, key: &Path, format: ArchiveFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let read_archive = fs::read(input)?; // read input path (archive)
     let read_key = fs::read(key)?; // read key path
 
     let key = Key::from_slice(&read_key); // convert array
     let cipher = ChaCha20Poly1305::new(key); // create encrypted value
 
-    let mut nonce = [0u8; 12]; // nonce array limited 12bytes
+    let mut nonce = [0u8; NONCE_LEN]; // nonce array limited 12bytes
     OsRng.fill_bytes(&mut nonce);
     let nonce = Nonce::from_slice(&nonce); // convert array to nonce
 
-    // encrypt key and convert read_zip to &[u8]
-    let cipher_text = cipher.encrypt(nonce, read_zip.as_ref()).map_err(|e| format!("Error to code: {:?}", e))?;
+    // encrypt archive binding the header to the cipher text
+    let header = pkg_header(format);
+    let payload = Payload { msg: read_archive.as_ref(), aad: &header };
+    let cipher_text = cipher.encrypt(nonce, payload).map_err(|e| format!("Error to code: {:?}", e))?;
 
-    let mut content = Vec::with_capacity(nonce.len() + cipher_text.iter().len());
+    let mut content = Vec::with_capacity(HEADER_LEN + nonce.len() + cipher_text.len());
+    content.extend_from_slice(&header); // add header
     content.extend_from_slice(nonce); // add elements in nonce
     content.extend_from_slice(&cipher_text); // wait a &[u8]
 
@@ -58,25 +45,193 @@ pub fn encrypt_zip(input: &Path, output: &Path, key: &Path) -> Result<(), Box<dy
     Ok(())
 }
 
-// sign .pkg with priv key
-pub fn sign_pkg(pkg_path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let pkg_data = fs::read(pkg_path)?; // read content .pkg
+// to decrypt pkg content, returns archive format and plain archive
+// packages without header are legacy zip containers
+pub fn decrypt_pkg(pkg_data: &[u8], key_bytes: &[u8]) -> Result<(ArchiveFormat, Vec<u8>), Box<dyn std::error::Error>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key_bytes));
+
+    let (format, aad, body) = if pkg_data.starts_with(PKG_MAGIC) {
+        if pkg_data.len() < HEADER_LEN + NONCE_LEN {
+            return Err("Package is truncated".into());
+        }
+        let (header, body) = pkg_data.split_at(HEADER_LEN);
+        if header[4] != PKG_VERSION {
+            return Err(format!("Unsupported package version: {}", header[4]).into());
+        }
+        let format = ArchiveFormat::from_id(header[5])
+            .ok_or_else(|| format!("Unknown archive format id: {}", header[5]))?;
+        (format, header, body)
+    } else {
+        if pkg_data.len() < NONCE_LEN {
+            return Err("Package is truncated".into());
+        }
+        (ArchiveFormat::Zip, &[][..], pkg_data)
+    };
+
+    // devide nonce and cipher_text
+    let (nonce_bytes, ciphertext) = body.split_at(NONCE_LEN);
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    // decrypt pkg
+    let decrypted_data = cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad })
+        .map_err(|e| format!("Decryption failed: {:?}", e))?;
+
+    Ok((format, decrypted_data))
+}
+
+// build a source dir (or the commit git_rev of a git repository) into an encrypted package in the storage backend
+// any failure is returned, a post-build hook failure leaves the package built and registered
+pub async fn build_pkg(
+    path: PathBuf,
+    name: &str,
+    version: Option<String>,
+    author: Option<String>,
+    format: ArchiveFormat,
+    git_rev: Option<&str>,
+    no_hooks: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("🚧 package build:");
+    let started_on = keys::signing_time();
+
+    // clean export of the commit, removed when the build ends
+    let checkout = match git_rev {
+        Some(rev) => {
+            let checkout = GitCheckout::export(&path.to_string_lossy(), Some(rev)).map_err(|e| format!("Error exporting git tree: {e}"))?;
+            println!("🌿 Exported {} at {}", checkout.repository, checkout.commit);
+            Some(checkout)
+        }
+        None => None,
+    };
+    let version = match (version, &checkout) {
+        (Some(version), _) => version,
+        (None, Some(checkout)) => checkout.version()?,
+        (None, None) => unreachable!("clap requires a version without --git"),
+    };
+    let path = checkout.as_ref().map(|c| c.dir.clone()).unwrap_or(path);
+    if !path.is_dir() {
+        return Err(format!("Source dir {} not found", path.display()).into());
+    }
+    println!("Path: {path:?}, Name: {name}, Version: {version}, Author: {:?}, Format: {format}", author);
+
+    // build hooks of securepkg.toml, pre-build runs before the files are hashed
+    let hooks = BuildHooks::load(&path).map_err(|e| format!("Error reading build hooks: {e}"))?;
+    let mut runner = if hooks.is_empty() {
+        None
+    } else if no_hooks {
+        println!("⚠️ Build hooks skipped (--no-hooks)");
+        None
+    } else {
+        Some(HookRunner::new(hooks, &path, name, &version).map_err(|e| format!("Error preparing build hooks: {e}"))?)
+    };
+    if let Some(hooks) = &runner {
+        hooks.run(Stage::PreBuild).await?;
+        println!("📜 Hook output logged to {}", hooks.log_path.display());
+    }
+
+    // manifest embedded in the archive
+    let mut manifest = Manifest::load(&path, name, &version, author.clone()).map_err(|e| format!("Error reading manifest: {e}"))?;
+    manifest.commit = checkout.as_ref().map(|c| c.commit.clone());
+    manifest.provenance = true;
+    let manifest_entry = manifest.to_entry().map_err(|e| format!("Error writing manifest: {e}"))?;
+    // CycloneDX and SPDX documents embedded next to the manifest
+    let sbom_entries = sbom::to_entries(&manifest, &path).map_err(|e| format!("Error writing SBOM: {e}"))?;
+
+    let filename = format!("{}-{}.{}", name, version, format.extension());
+    let input = storage::get_securepkg_dir().join(filename);
+
+    let meta_entries: Vec<_> = std::iter::once(manifest_entry).chain(sbom_entries).collect();
+    archive::pack_dir(&path, &input, format, &meta_entries).map_err(|e| format!("Error creating package: {e}"))?;
+    println!("✅ Package created at {:?}", input);
+
+    let store = blob::open().map_err(|e| format!("Error opening package storage: {e}"))?;
+    let pkg_key = blob::pkg_key(name, &version);
+    let output = storage::get_securepkg_dir().join(&pkg_key);
+    let key = storage::get_key_path();
+
+    let encrypted = encrypt_archive(&input, &output, &key, format);
+    let _ = fs::remove_file(&input); // plain archive is no longer needed
+    encrypted.map_err(|e| format!("Error encrypting file: {e}"))?;
+    println!("🔐 archive encrypted correctly {:?}", output);
+
+    // connect and save pkg into DB
+    let data_pkg = fs::read(&output);
+    let _ = fs::remove_file(&output); // blob now lives in the storage backend
+    let data_pkg = data_pkg.map_err(|e| format!("Error reading package: {e}"))?;
+    let hash_hex = hex::encode(Sha256::digest(&data_pkg));
+
+    // how the package was built, signed with the publisher key
+    let statement = provenance::record(&manifest, &format.to_string(), &path, checkout.as_ref(), &pkg_key, &data_pkg, started_on)
+        .map_err(|e| format!("Error recording provenance: {e}"))?;
+
+    store.put(&pkg_key, data_pkg).await.map_err(|e| format!("Error storing package: {e}"))?;
+    println!("🗃️ Package stored in {}", store.describe());
+    store.put(&blob::sibling(&pkg_key, PROVENANCE_EXTENSION), statement).await.map_err(|e| format!("Error storing provenance: {e}"))?;
+    println!("🏗️ Provenance recorded");
+    // signatures of an earlier build no longer match
+    store.delete(&blob::sibling(&pkg_key, "sig")).await.map_err(|e| format!("Error removing old signature: {e}"))?;
+
+    let conn = orm::connectdb().await.map_err(|e| format!("Error to connect DB: '{e}'"))?;
+    println!("🔗 DB connected correctly");
+
+    let recorded = match &checkout {
+        Some(checkout) => orm::sources_fn::save_source(&conn, name, &version, &checkout.repository, &checkout.commit).await,
+        None => orm::sources_fn::remove_source(&conn, name, &version).await,
+    };
+    recorded.map_err(|e| format!("Error recording git source: {e}"))?;
+    if let Some(hooks) = &mut runner {
+        hooks.set("SECUREPKG_PKG_KEY", &pkg_key);
+        hooks.set("SECUREPKG_PKG_SHA256", &hash_hex);
+    }
+    orm::publish_fn::insert_package(&conn, name.to_string(), version, author, Some(hash_hex), Some(pkg_key)).await
+        .map_err(|e| format!("Error inserting into database: {e}"))?;
+    println!("📦 Package inserted into database");
 
-    // read private key path
-    let priv_key_path = dirs::home_dir()
-        .expect("Could not get HOME directory")
-        .join(".securepkg")
-        .join("keys")
-        .join("private.pem");
+    // the package stays built and stored when a post-build hook fails
+    if let Some(hooks) = &runner {
+        hooks.run(Stage::PostBuild).await.map_err(|e| format!("Package built, but {e}"))?;
+    }
+    Ok(())
+}
+
+// sign .pkg with priv key, add keeps the signatures of co-signers
+pub fn sign_pkg(pkg_path: &Path, add: bool) -> Result<SignatureSet, Box<dyn std::error::Error>> {
+    let pkg_data = fs::read(pkg_path)?; // read content .pkg
+    let mut set = if add { SignatureSet::read(pkg_path) } else { SignatureSet::default() };
+    set.sign(&pkg_data)?; // hash pkg content and sign
+    Ok(set)
+}
 
-    let pem = fs::read_to_string(priv_key_path)?;
-    let private_key = RsaPrivateKey::from_pkcs1_pem(&pem)?;
+// sign a package of the storage backend, record its signatures and log them, returns its local path
+pub async fn sign_stored(conn: &DatabaseConnection, name: &str, version: &str, add: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let pkg = orm::publish_fn::find_pkg(conn, name, version).await?
+        .ok_or("Package not found in database")?;
+    let store = blob::open()?;
+    let pkg_key = blob::key_of(pkg.encrypted_path.as_deref().unwrap_or_default());
+    let pkg_path = blob::pkg_path(store.as_ref(), &pkg_key).await?
+        .ok_or_else(|| format!("{pkg_key} not found in {}", store.describe()))?;
+
+    let set = sign_pkg(&pkg_path, add)?;
+    println!("🖊️ Package successfully signed ({} signature(s))", set.signatures.len());
+
+    // save next to the package
+    let sig_key = blob::sibling(&pkg_key, "sig");
+    let sig_data = set.to_bytes()?;
+    store.put(&sig_key, sig_data.clone()).await?;
+    println!("💾 Signature saved to: {sig_key} in {}", store.describe());
+    if store.local_root().is_none() {
+        fs::write(pkg_path.with_extension("sig"), &sig_data)?; // keep the cached copy in sync
+    }
 
-    // hash pkg content and sign
-    let digest = Sha256::digest(&pkg_data);
-    let signature = private_key.sign(Pkcs1v15Sign::new::<Sha256>(), &digest)?;
+    // save sign in db
+    orm::publish_fn::update_signature(conn, name, version, sig_data).await?;
+    orm::signatures_fn::replace_signatures(conn, name, version, &set.signatures).await?;
+    println!("🗄️ Signature updated in database");
 
-    Ok(signature)
+    // serve and export hand out stored signatures, so each set is logged
+    let proof = translog::record_publish(conn, name, version).await?;
+    println!("📜 Logged at index {} (tree size {})", proof.leaf_index, proof.tree_head.signed.tree_size);
+    Ok(pkg_path)
 }
 
 // export pkg
@@ -89,10 +244,13 @@ pub async fn export_pkg(name: &str, version: &str, conn: &DatabaseConnection, re
     };
 
     // validate if package has encrypted path
-    let encrypted_path = match pkg.encrypted_path {
-        Some(path) => PathBuf::from(path),
+    let pkg_key = match pkg.encrypted_path {
+        Some(path) => blob::key_of(&path),
         None => return Err("Package has no encrypted path.".into()),
     };
+    let store = blob::open()?;
+    let pkg_data = store.get(&pkg_key).await?
+        .ok_or_else(|| format!("{pkg_key} not found in {}", store.describe()))?;
 
     // determine destination dir
     let export_dir = match repo {
@@ -108,27 +266,47 @@ pub async fn export_pkg(name: &str, version: &str, conn: &DatabaseConnection, re
     }
 
     // add filename and copy to export_path
-    let export_path = export_dir.join(encrypted_path.file_name().unwrap());
-    fs::copy(&encrypted_path, &export_path)?;
+    let export_path = export_dir.join(&pkg_key);
+    fs::write(&export_path, pkg_data)?;
     println!("📤 Package exported to {:?}", export_path);
 
     // copy .sig if exists
-    let sig_src = encrypted_path.with_extension("sig");
-    let sig_dst = export_dir.join(sig_src.file_name().unwrap());
-    
-    if sig_src.exists() {
-        fs::copy(&sig_src, &sig_dst)?;
-        println!("🖊️ Signature file exported to {:?}", sig_dst);
-    } else {
-        println!("⚠️ No signature file found to export");
+    let sig_dst = export_path.with_extension("sig");
+    match store.get(&blob::sibling(&pkg_key, "sig")).await? {
+        Some(sig) => {
+            fs::write(&sig_dst, sig)?;
+            println!("🖊️ Signature file exported to {:?}", sig_dst);
+        }
+        None => println!("⚠️ No signature file found to export"),
+    }
+
+    // inclusion proof and the log it belongs to, signatures from before the log are logged now
+    let proof_key = blob::sibling(&pkg_key, PROOF_EXTENSION);
+    let proof = store.get(&proof_key).await?;
+    if sig_dst.exists() && !proof.as_deref().map(|p| translog::covers(p, &export_path)).transpose()?.unwrap_or(false) {
+        translog::record_publish(conn, name, version).await?;
+        println!("📜 Signatures logged");
+    }
+    if let Some(proof) = store.get(&proof_key).await? {
+        fs::write(export_path.with_extension(PROOF_EXTENSION), proof)?;
+        fs::write(export_dir.join(LOG_FILE), serde_json::to_vec_pretty(&translog::log_file(conn).await?)?)?;
+        println!("📜 Inclusion proof and {LOG_FILE} exported");
+    }
+
+    // signed build provenance
+    if let Some(statement) = store.get(&blob::sibling(&pkg_key, PROVENANCE_EXTENSION)).await? {
+        fs::write(export_path.with_extension(PROVENANCE_EXTENSION), statement)?;
+        println!("🏗️ Provenance exported");
     }
 
+    // keep the signed index of the export dir up to date, with the yank and deprecation flags
+    repo::update_index(&export_dir, name, version, &export_path, status::load(conn, name, version).await?)?;
+
     Ok(())
 }
 
-// install pkg: validate signature, decrypt pkg to zip and then zip to file
-pub async fn install_pkg(name: &str, version: &str, from_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
-    // search and validate if package exists
+// find package file: given path, exports folder, package storage or configured repositories
+pub async fn locate_pkg(name: &str, version: &str, from_file: Option<&Path>) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let pkg_path = if let Some(path) = from_file {
         PathBuf::from(path)
     } else {
@@ -136,60 +314,307 @@ pub async fn install_pkg(name: &str, version: &str, from_file: Option<&Path>) ->
     };
     
     if pkg_path.exists() {
-        println!("📦 Found exported package at {}", pkg_path.display());
-    } else {
-        return Err("Package not found in exports folder.".into());
+        return Ok(pkg_path);
+    }
+    if from_file.is_some() {
+        return Err("Package file not found.".into());
+    }
+
+    // packages built here, only once signed
+    let store = blob::open()?;
+    let pkg_key = blob::pkg_key(name, version);
+    if store.exists(&blob::sibling(&pkg_key, "sig")).await?
+        && let Some(path) = blob::pkg_path(store.as_ref(), &pkg_key).await?
+    {
+        return Ok(path);
+    }
+
+    // look in configured repositories
+    match repo::locate_in_repos(name, version).await? {
+        Some(path) => Ok(path),
+        None => Err("Package not found in exports folder, package storage or configured repositories.".into()),
     }
+}
 
-    // validate sign
+// validate .sig next to the package against trusted keys and the signature policy of name,
+// its .proof against the transparency log and its provenance against the package hash, returns package content
+pub fn verify_pkg(name: &str, pkg_path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let sign_path = pkg_path.with_extension("sig");
     let sign = fs::read(&sign_path).map_err(|e| format!("Signature file not found: {}", e))?;
 
-    let pub_key_path = storage::get_securepkg_dir().join("keys").join("public.pem");
-    let pub_pem = fs::read_to_string(&pub_key_path)?;
-    let public_key = RsaPublicKey::from_pkcs1_pem(&pub_pem)?;
+    let pkg_data = fs::read(pkg_path)?;
+    signatures::verify(name, &pkg_data, &SignatureSet::parse(&sign).anchored(pkg_path, &pkg_data))?;
+    translog::check(pkg_path, &pkg_data)?;
+    provenance::check(pkg_path, &pkg_data)?;
 
-    let pkg_data = fs::read(&pkg_path)?;
-    let digest = Sha256::digest(&pkg_data);
-    let verifier = Pkcs1v15Sign::new::<Sha256>();
-    verifier.verify(&public_key, &digest, &sign).map_err(|_| "Invalid signature")?;
+    Ok(pkg_data)
+}
 
-    println!("🧾 Signature verified successfully");
+// verify and decrypt a package in memory
+pub fn open_pkg(name: &str, pkg_path: &Path) -> Result<(ArchiveFormat, Vec<ArchiveEntry>), Box<dyn std::error::Error>> {
+    read_pkg(&verify_pkg(name, pkg_path)?)
+}
 
-    // DECRYPT PKG TO ZIP
+// decrypt package content returned by verify_pkg
+pub fn read_pkg(pkg_data: &[u8]) -> Result<(ArchiveFormat, Vec<ArchiveEntry>), Box<dyn std::error::Error>> {
     // read secret key
-    let key_path = storage::get_key_path();
-    let key_bytes = fs::read(&key_path)?;
-    
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key_bytes));
+    let key_bytes = fs::read(storage::get_key_path())?;
+    let (format, decrypted_data) = decrypt_pkg(pkg_data, &key_bytes)?;
+    let entries = archive::read_entries(&decrypted_data, format)?;
 
-    // devide nonce and cipher_text
-    let (nonce_bytes, ciphertext) = pkg_data.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
-    
-    // decrypt pkg
-    let decrypted_data = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| format!("Decryption failed: {:?}", e))?;
+    Ok((format, entries))
+}
+
+// install pkg: validate signature, decrypt pkg to archive, check the install policy and then archive to files
+// explain prints every rule of the install policy
+pub async fn install_pkg(name: &str, version: &str, from_file: Option<&Path>, conn: &DatabaseConnection, explain: bool, allow_scripts: bool, allow_unsandboxed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // search and validate if package exists
+    let pkg_path = locate_pkg(name, version, from_file).await?;
+    println!("📦 Found package at {}", pkg_path.display());
+    status::lookup(conn, name, version).await?.warn(name, version);
+
+    // validate sign and decrypt
+    let pkg_data = verify_pkg(name, &pkg_path)?;
+    let (format, entries) = read_pkg(&pkg_data)?;
+    println!("🧾 Signature verified successfully");
+    println!("🗜️ Archive format: {format}");
+
+    // install policy sees the verified content only
+    let manifest = Manifest::from_entries(&entries)?;
+    provenance::require(&pkg_path, manifest.as_ref())?;
+    policy::check_pkg(name, &pkg_path, &pkg_data, manifest.as_ref(), &entries, explain)?;
+
+    // check every payload file against the signed manifest
+    match &manifest {
+        Some(m) if !m.files.is_empty() => {
+            m.verify_files(&payload_files(&entries))?;
+            println!("🌳 {} files match Merkle root {}", m.files.len(), m.merkle_root.as_deref().unwrap_or_default());
+        }
+        _ => println!("⚠️ Package has no per-file manifest, skipping file verification"),
+    }
+    if let Some(m) = manifest.as_ref().filter(|m| !m.scripts.is_empty()) {
+        scripts::consent(m, &entries, allow_scripts)?;
+    }
+
+    // extract into a staging folder, then move it to .securepkg/installed
+    let installed_dir = storage::get_installed_dir();
+    let install_path = installed_dir.join(format!("{}-{}", name, version));
+    let staging_path = installed_dir.join(format!(".staging-{}-{}", name, version));
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)?;
+    }
+    fs::create_dir_all(&staging_path)?;
+
+    // extract files to staging dir, metadata stays in the package
+    archive::extract(entries.iter().filter(|e| !is_meta(&e.path)), &staging_path)?;
 
+    // verify what was written to disk before replacing the install dir
+    if let Some(m) = manifest.as_ref().filter(|m| !m.files.is_empty())
+        && let Err(e) = m.verify_files(&hash_dir(&staging_path)?)
+    {
+        fs::remove_dir_all(&staging_path)?;
+        return Err(e);
+    }
 
-    // EXTRACT ZIP TO FOLDER
-    // save temporally in temp.zip
-    let temp_zip = storage::get_securepkg_dir().join("temp.zip");
-    fs::write(&temp_zip, &decrypted_data)?;
+    // record the installed version, legacy packages only get name and version
+    let mut manifest = manifest.unwrap_or_default();
+    manifest.name = name.to_string();
+    manifest.version = version.to_string();
 
-    // create .securepkg/install folder
-    let install_path = storage::get_securepkg_dir()
-    .join("installed")
-    .join(format!("{}-{}", name, version));
-    fs::create_dir_all(&install_path)?;
-    
-    let zip_file = File::open(&temp_zip)?; // open zip file
-    let mut file = zip::ZipArchive::new(zip_file)?; // read zip file
-    file.extract(&install_path)?; // extract file to install dir
+    // pre-install sees the verified files, a failure leaves nothing installed
+    if let Err(e) = scripts::run(&manifest, ScriptStage::PreInstall, &staging_path, allow_unsandboxed).await {
+        fs::remove_dir_all(&staging_path)?;
+        return Err(e);
+    }
+    manifest.save_installed()?;
+    sbom::save_installed(&entries, name, version)?;
+
+    if install_path.exists() {
+        fs::remove_dir_all(&install_path)?;
+    }
+    fs::rename(&staging_path, &install_path)?;
     println!("📁 Package extracted to: {}", install_path.display());
 
-    fs::remove_file(&temp_zip)?; // remove temporal file
+    // post-install failures roll the install back before it is recorded
+    if let Err(e) = scripts::run(&manifest, ScriptStage::PostInstall, &install_path, allow_unsandboxed).await {
+        fs::remove_dir_all(&install_path)?;
+        fs::remove_file(Manifest::installed_path(name, version))?;
+        sbom::remove_installed(name, version)?;
+        return Err(e);
+    }
+
+    // record version, first installed version becomes the active one
+    orm::installed_fn::record_install(conn, name, version).await?;
+    match orm::installed_fn::find_active(conn, name).await? {
+        Some(active) if active.version != version => {
+            println!("ℹ️ Active version is still {}, run `use {} {}` to switch", active.version, name, version);
+        }
+        Some(_) => {}
+        None => activate_version(conn, name, version).await?,
+    }
+
+    Ok(())
+}
+
+// mark a version active and point installed/current/<name> to it
+pub async fn activate_version(conn: &DatabaseConnection, name: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let install_path = storage::get_installed_dir().join(format!("{}-{}", name, version));
+    if !install_path.exists() {
+        return Err(format!("{name} {version} is not installed").into());
+    }
+
+    orm::installed_fn::set_active(conn, name, version).await?;
+
+    // create the new link next to the old one and rename it over, so the switch is atomic
+    let current_dir = storage::get_current_dir();
+    fs::create_dir_all(&current_dir)?;
+    let link = current_dir.join(name);
+    let tmp_link = current_dir.join(format!(".{name}.tmp"));
+    if tmp_link.symlink_metadata().is_ok() {
+        fs::remove_file(&tmp_link)?;
+    }
+    symlink(&Path::new("..").join(format!("{}-{}", name, version)), &tmp_link)?;
+    fs::rename(&tmp_link, &link)?;
+
+    println!("🔗 {} {} is now active: {}", name, version, link.display());
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+// remove an installed version, its recorded manifest and SBOMs and its current link
+pub async fn remove_installed(conn: &DatabaseConnection, name: &str, version: &str, allow_unsandboxed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // pre-remove was approved with the install, a failure keeps the version installed
+    let install_path = storage::get_installed_dir().join(format!("{}-{}", name, version));
+    if let Some(manifest) = Manifest::load_installed(name, version)?
+        && install_path.exists()
+    {
+        scripts::run(&manifest, ScriptStage::PreRemove, &install_path, allow_unsandboxed).await?;
+    }
 
+    let active = orm::installed_fn::find_active(conn, name).await?;
+    if active.is_some_and(|a| a.version == version) {
+        let link = storage::get_current_dir().join(name);
+        if link.symlink_metadata().is_ok() {
+            fs::remove_file(&link)?;
+        }
+    }
+    orm::installed_fn::remove_install(conn, name, version).await?;
+
+    if install_path.exists() {
+        fs::remove_dir_all(&install_path)?;
+    }
+
+    let manifest_path = Manifest::installed_path(name, version);
+    if manifest_path.exists() {
+        fs::remove_file(&manifest_path)?;
+    }
+    sbom::remove_installed(name, version)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, home, temp_dir};
+
+    fn packed(format: ArchiveFormat) -> PathBuf {
+        let src = temp_dir("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("hello.txt"), "hello").unwrap();
+        fs::write(src.join("bin/run.sh"), "echo run").unwrap();
+        let plain = temp_dir("plain").join(format!("pkg.{}", format.extension()));
+        archive::pack_dir(&src, &plain, format, &[]).unwrap();
+        plain
+    }
+
+    fn files(entries: &[ArchiveEntry]) -> Vec<(String, Vec<u8>)> {
+        let mut files: Vec<_> = entries.iter().filter(|e| !e.is_dir).map(|e| (e.path.clone(), e.data.clone())).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn every_format_round_trips_through_the_container() {
+        home();
+        let key = fs::read(storage::get_key_path()).unwrap();
+        let expected = vec![("bin/run.sh".to_string(), b"echo run".to_vec()), ("hello.txt".to_string(), b"hello".to_vec())];
+
+        for format in [ArchiveFormat::Zip, ArchiveFormat::Stored, ArchiveFormat::TarZst, ArchiveFormat::TarXz] {
+            let plain = packed(format);
+            let pkg = plain.with_extension("pkg");
+            encrypt_archive(&plain, &pkg, &storage::get_key_path(), format).unwrap();
+
+            let (found, archive) = decrypt_pkg(&fs::read(&pkg).unwrap(), &key).unwrap();
+            assert_eq!(found, format);
+            assert_eq!(files(&archive::read_entries(&archive, found).unwrap()), expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn headerless_legacy_packages_still_decrypt() {
+        home();
+        let key = fs::read(storage::get_key_path()).unwrap();
+        let archive = fs::read(packed(ArchiveFormat::Zip)).unwrap();
+
+        // before the header a .pkg was the nonce and the cipher text without associated data
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let nonce = [7u8; NONCE_LEN];
+        let mut legacy = nonce.to_vec();
+        legacy.extend(cipher.encrypt(Nonce::from_slice(&nonce), archive.as_slice()).unwrap());
+
+        let (format, plain) = decrypt_pkg(&legacy, &key).unwrap();
+        assert_eq!(format, ArchiveFormat::Zip);
+        assert_eq!(plain, archive);
+    }
+
+    #[test]
+    fn tampered_format_byte_is_rejected() {
+        home();
+        let key = fs::read(storage::get_key_path()).unwrap();
+        let plain = packed(ArchiveFormat::TarZst);
+        let pkg = plain.with_extension("pkg");
+        encrypt_archive(&plain, &pkg, &storage::get_key_path(), ArchiveFormat::TarZst).unwrap();
+
+        let mut data = fs::read(&pkg).unwrap();
+        data[5] = ArchiveFormat::TarXz.id();
+        let err = decrypt_pkg(&data, &key).unwrap_err().to_string();
+        assert!(err.contains("Decryption failed"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn use_switches_the_current_link_and_refuses_missing_versions() {
+        home();
+        let conn = testutil::db().await;
+        for version in ["1.0.0", "2.0.0"] {
+            fs::create_dir_all(storage::get_installed_dir().join(format!("use-switch-{version}"))).unwrap();
+            orm::installed_fn::record_install(&conn, "use-switch", version).await.unwrap();
+        }
+        let link = storage::get_current_dir().join("use-switch");
+        let tmp_link = storage::get_current_dir().join(".use-switch.tmp");
+
+        activate_version(&conn, "use-switch", "1.0.0").await.unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("../use-switch-1.0.0"));
+
+        // a link left behind by an interrupted switch does not block the next one
+        std::os::unix::fs::symlink("../use-switch-1.0.0", &tmp_link).unwrap();
+        activate_version(&conn, "use-switch", "2.0.0").await.unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("../use-switch-2.0.0"));
+        assert!(tmp_link.symlink_metadata().is_err());
+        assert_eq!(orm::installed_fn::find_active(&conn, "use-switch").await.unwrap().unwrap().version, "2.0.0");
+
+        let err = activate_version(&conn, "use-switch", "3.0.0").await.unwrap_err().to_string();
+        assert_eq!(err, "use-switch 3.0.0 is not installed");
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("../use-switch-2.0.0"));
+        assert_eq!(orm::installed_fn::find_active(&conn, "use-switch").await.unwrap().unwrap().version, "2.0.0");
+    }
+}
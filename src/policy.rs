@@ -0,0 +1,196 @@
+use std::{fs, path::{Path, PathBuf}};
+use rsa::traits::PublicKeyParts;
+use serde::{Deserialize, Serialize};
+use crate::{archive::ArchiveEntry, keys, manifest::{is_meta, Manifest}, signatures::SignatureSet, storage};
+
+// install rules of the security team, read from SECUREPKG_POLICY or ~/.securepkg/policy.toml
+// unset rules are not enforced, unsigned packages are always refused
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct InstallPolicy {
+    // at least one valid signature must come from these key ids
+    #[serde(default)]
+    pub approved_keys: Vec<String>,
+    // minimum RSA modulus size of every key with a valid signature
+    pub min_key_bits: Option<usize>,
+    // the manifest license must be one of these
+    #[serde(default)]
+    pub licenses: Vec<String>,
+    // maximum .pkg size in bytes
+    pub max_size: Option<u64>,
+    // file extensions refused anywhere in the package, without the dot
+    #[serde(default)]
+    pub forbidden_extensions: Vec<String>,
+    // true runs install scripts without asking, false refuses packages that have any
+    pub allow_scripts: Option<bool>,
+    // true runs install scripts even where landlock cannot confine their writes
+    pub allow_unsandboxed: Option<bool>,
+}
+
+// outcome of one rule: what was found when it passes, the violation otherwise, None when not configured
+pub struct RuleResult {
+    pub rule: &'static str,
+    pub outcome: Option<Result<String, String>>,
+}
+
+impl RuleResult {
+    fn unset(rule: &'static str) -> Self {
+        RuleResult { rule, outcome: None }
+    }
+
+    fn check(rule: &'static str, outcome: Result<String, String>) -> Self {
+        RuleResult { rule, outcome: Some(outcome) }
+    }
+}
+
+pub fn policy_path() -> PathBuf {
+    std::env::var("SECUREPKG_POLICY").map(PathBuf::from).unwrap_or_else(|_| storage::get_policy_path())
+}
+
+impl InstallPolicy {
+    // None when no policy file exists
+    pub fn load() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = policy_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let policy = toml::from_str(&fs::read_to_string(&path)?).map_err(|e| format!("Invalid install policy {}: {e}", path.display()))?;
+        Ok(Some(policy))
+    }
+
+    // evaluate every rule against a package, its trusted signers and its decrypted content
+    pub fn evaluate(&self, pkg_data: &[u8], signers: &[String], manifest: Option<&Manifest>, entries: &[ArchiveEntry]) -> Result<Vec<RuleResult>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+
+        results.push(RuleResult::check("signed", match signers {
+            [] => Err("package has no valid signature from a trusted key".to_string()),
+            _ => Ok(format!("signed by {}", signers.join(", "))),
+        }));
+
+        results.push(if self.approved_keys.is_empty() {
+            RuleResult::unset("approved keys")
+        } else {
+            RuleResult::check("approved keys", match signers.iter().find(|s| self.approved_keys.contains(s)) {
+                Some(signer) => Ok(format!("signed by approved key {signer}")),
+                None => Err(format!("no signature from an approved key [{}]", self.approved_keys.join(", "))),
+            })
+        });
+
+        results.push(match self.min_key_bits {
+            None => RuleResult::unset("key size"),
+            Some(min) => {
+                let keys = keys::all_keys()?;
+                let mut weak = Vec::new();
+                for signer in signers {
+                    if let Some((_, key)) = keys.iter().find(|(id, _)| id == signer)
+                        && key.n().bits() < min
+                    {
+                        weak.push(format!("{signer} has {} bits", key.n().bits()));
+                    }
+                }
+                RuleResult::check("key size", if weak.is_empty() {
+                    Ok(format!("every signing key has at least {min} bits"))
+                } else {
+                    Err(format!("{}, at least {min} required", weak.join(", ")))
+                })
+            }
+        });
+
+        results.push(if self.licenses.is_empty() {
+            RuleResult::unset("license")
+        } else {
+            let allowed = self.licenses.join(", ");
+            RuleResult::check("license", match manifest.and_then(|m| m.license.as_deref()) {
+                Some(license) if self.licenses.iter().any(|l| l == license) => Ok(format!("{license} is allowed")),
+                Some(license) => Err(format!("{license} is not one of [{allowed}]")),
+                None => Err(format!("package declares no license, one of [{allowed}] is required")),
+            })
+        });
+
+        results.push(match self.max_size {
+            None => RuleResult::unset("size"),
+            Some(max) => {
+                let size = pkg_data.len() as u64;
+                RuleResult::check("size", if size <= max {
+                    Ok(format!("{size} bytes, at most {max}"))
+                } else {
+                    Err(format!("package is {size} bytes, at most {max} allowed"))
+                })
+            }
+        });
+
+        results.push(if self.forbidden_extensions.is_empty() {
+            RuleResult::unset("file types")
+        } else {
+            let forbidden: Vec<&str> = entries.iter()
+                .filter(|e| !e.is_dir && !is_meta(&e.path))
+                .map(|e| e.path.as_str())
+                .filter(|path| {
+                    Path::new(path).extension().and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| self.forbidden_extensions.iter().any(|f| f.eq_ignore_ascii_case(ext)))
+                })
+                .collect();
+            RuleResult::check("file types", if forbidden.is_empty() {
+                Ok(format!("no .{} files", self.forbidden_extensions.join(", .")))
+            } else {
+                Err(format!("forbidden files {}", forbidden.join(", ")))
+            })
+        });
+
+        results.push(match self.allow_scripts {
+            None => RuleResult::unset("install scripts"),
+            Some(allow) => {
+                let stages: Vec<String> = manifest.map(|m| m.scripts.list().iter().map(|(stage, _)| stage.to_string()).collect()).unwrap_or_default();
+                RuleResult::check("install scripts", match (allow, stages.is_empty()) {
+                    (_, true) => Ok("no install scripts".to_string()),
+                    (true, false) => Ok(format!("{} approved", stages.join(", "))),
+                    (false, false) => Err(format!("package has {} scripts", stages.join(", "))),
+                })
+            }
+        });
+
+        Ok(results)
+    }
+}
+
+// print every rule with its outcome
+pub fn explain(results: &[RuleResult]) {
+    println!("🛡️ Install policy {}:", policy_path().display());
+    for result in results {
+        match &result.outcome {
+            Some(Ok(detail)) => println!("  ✅ {}: {detail}", result.rule),
+            Some(Err(violation)) => println!("  ❌ {}: {violation}", result.rule),
+            None => println!("  ➖ {}: not configured", result.rule),
+        }
+    }
+}
+
+// one error listing every violation
+pub fn enforce(name: &str, results: &[RuleResult]) -> Result<(), Box<dyn std::error::Error>> {
+    let violations: Vec<String> = results.iter()
+        .filter_map(|r| match &r.outcome {
+            Some(Err(violation)) => Some(format!("  - {}: {violation}", r.rule)),
+            _ => None,
+        })
+        .collect();
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(format!("{name} violates the install policy:\n{}", violations.join("\n")).into())
+}
+
+// evaluate the install policy, if any, against a package verified and decrypted by install
+pub fn check_pkg(name: &str, pkg_path: &Path, pkg_data: &[u8], manifest: Option<&Manifest>, entries: &[ArchiveEntry], explain_rules: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(policy) = InstallPolicy::load()? else {
+        if explain_rules {
+            println!("🛡️ No install policy at {}", policy_path().display());
+        }
+        return Ok(());
+    };
+
+    let signers = SignatureSet::read(pkg_path).anchored(pkg_path, pkg_data).trusted_signers(pkg_data)?;
+    let results = policy.evaluate(pkg_data, &signers, manifest, entries)?;
+    if explain_rules {
+        explain(&results);
+    }
+    enforce(name, &results)
+}
@@ -0,0 +1,307 @@
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{git::GitCheckout, keys, manifest::Manifest, translog::{self, Logged}};
+
+// build provenance: an in-toto statement with a SLSA provenance predicate,
+// signed by the publisher key in a DSSE envelope stored next to the .sig
+
+// extension of the signed provenance next to the .pkg
+pub const PROVENANCE_EXTENSION: &str = "intoto";
+pub const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+pub const BUILD_TYPE: &str = "https://github.com/Gonzaa21/securepkg/build/v1";
+
+// artifact or input with its digests, e.g. {"sha256": ...} or {"gitCommit": ...}
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ResourceDescriptor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub digest: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub predicate: Provenance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    pub external_parameters: ExternalParameters,
+    // the source tree first, then every input file
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExternalParameters {
+    pub name: String,
+    pub version: String,
+    pub format: String,
+    pub source: String,
+    pub command: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunDetails {
+    pub builder: Builder,
+    pub metadata: BuildMetadata,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Builder {
+    pub id: String,
+    #[serde(default)]
+    pub version: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    pub started_on: DateTime<Utc>,
+    pub finished_on: DateTime<Utc>,
+}
+
+// DSSE envelope, payload is the base64 statement
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub payload_type: String,
+    pub payload: String,
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvelopeSignature {
+    pub keyid: String,
+    pub sig: String,
+}
+
+// DSSE pre-authentication encoding, what is actually signed
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    [format!("DSSEv1 {} {payload_type} {} ", payload_type.len(), payload.len()).as_bytes(), payload].concat()
+}
+
+// provenance of a package just built from src_dir or a git export, signed with the local key
+pub fn record(
+    manifest: &Manifest,
+    format: &str,
+    src_dir: &Path,
+    checkout: Option<&GitCheckout>,
+    pkg_file: &str,
+    pkg_data: &[u8],
+    started_on: DateTime<Utc>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (source, descriptor) = match checkout {
+        Some(checkout) => (checkout.repository.clone(), git_descriptor(&checkout.repository, &checkout.commit, false)),
+        None => {
+            let source_path = fs::canonicalize(src_dir)?;
+            (source_path.display().to_string(), source_descriptor(&source_path))
+        }
+    };
+    let mut dependencies = vec![descriptor];
+    for file in &manifest.files {
+        dependencies.push(ResourceDescriptor {
+            name: Some(file.path.clone()),
+            digest: BTreeMap::from([("sha256".to_string(), file.sha256.clone())]),
+            ..Default::default()
+        });
+    }
+
+    let statement = Statement {
+        statement_type: STATEMENT_TYPE.to_string(),
+        subject: vec![ResourceDescriptor {
+            name: Some(pkg_file.to_string()),
+            digest: BTreeMap::from([("sha256".to_string(), hex::encode(Sha256::digest(pkg_data)))]),
+            ..Default::default()
+        }],
+        predicate_type: PREDICATE_TYPE.to_string(),
+        predicate: Provenance {
+            build_definition: BuildDefinition {
+                build_type: BUILD_TYPE.to_string(),
+                external_parameters: ExternalParameters {
+                    name: manifest.name.clone(),
+                    version: manifest.version.clone(),
+                    format: format.to_string(),
+                    source,
+                    command: std::env::args().collect(),
+                },
+                resolved_dependencies: dependencies,
+            },
+            run_details: RunDetails {
+                builder: Builder {
+                    id: builder_id(),
+                    version: BTreeMap::from([("securepkg".to_string(), env!("CARGO_PKG_VERSION").to_string())]),
+                },
+                metadata: BuildMetadata { started_on, finished_on: keys::signing_time() },
+            },
+        },
+    };
+
+    let payload = serde_json::to_vec(&statement)?;
+    let envelope = Envelope {
+        payload_type: PAYLOAD_TYPE.to_string(),
+        payload: general_purpose::STANDARD.encode(&payload),
+        signatures: vec![EnvelopeSignature {
+            keyid: keys::key_id(&keys::load_public_key()?)?,
+            sig: general_purpose::STANDARD.encode(keys::sign_bytes(&pae(PAYLOAD_TYPE, &payload))?),
+        }],
+    };
+    Ok(serde_json::to_vec_pretty(&envelope)?)
+}
+
+// git checkout of the source, with its commit and whether it had local changes, or the plain dir
+fn source_descriptor(src_dir: &Path) -> ResourceDescriptor {
+    let git = |args: &[&str]| {
+        Command::new("git").arg("-C").arg(src_dir).args(args).output().ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+    };
+
+    let Some(commit) = git(&["rev-parse", "HEAD"]) else {
+        return ResourceDescriptor { uri: Some(format!("file://{}", src_dir.display())), ..Default::default() };
+    };
+    let location = git(&["config", "--get", "remote.origin.url"])
+        .filter(|url| !url.is_empty())
+        .or_else(|| git(&["rev-parse", "--show-toplevel"]).map(|top| format!("file://{top}")))
+        .unwrap_or_default();
+    let dirty = git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty());
+    git_descriptor(&location, &commit, dirty)
+}
+
+fn git_descriptor(location: &str, commit: &str, dirty: bool) -> ResourceDescriptor {
+    ResourceDescriptor {
+        uri: Some(format!("git+{location}@{commit}")),
+        digest: BTreeMap::from([("gitCommit".to_string(), commit.to_string())]),
+        annotations: BTreeMap::from([("dirty".to_string(), dirty.to_string())]),
+        ..Default::default()
+    }
+}
+
+// user and host the build ran as
+fn builder_id() -> String {
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string());
+    let host = fs::read_to_string("/etc/hostname").ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "localhost".to_string());
+    format!("securepkg://{user}@{host}")
+}
+
+// check a signed provenance against the package it describes, returns the statement and its signer
+// the key is judged at the log time of the package (translog::judged_at), not the build's own finishedOn
+pub fn verify(envelope_data: &[u8], pkg_data: &[u8], logged: Option<&Logged>) -> Result<(Statement, String), Box<dyn std::error::Error>> {
+    let envelope: Envelope = serde_json::from_slice(envelope_data).map_err(|e| format!("Invalid provenance envelope: {e}"))?;
+    if envelope.payload_type != PAYLOAD_TYPE {
+        return Err(format!("Provenance payload type is {}, expected {PAYLOAD_TYPE}", envelope.payload_type).into());
+    }
+    let payload = general_purpose::STANDARD.decode(&envelope.payload)?;
+    let statement: Statement = serde_json::from_slice(&payload).map_err(|e| format!("Invalid provenance statement: {e}"))?;
+    if statement.statement_type != STATEMENT_TYPE || statement.predicate_type != PREDICATE_TYPE {
+        return Err(format!("Unsupported provenance {} / {}", statement.statement_type, statement.predicate_type).into());
+    }
+
+    // signed by a trusted key that is usable now or was when the package was logged
+    let signed = pae(&envelope.payload_type, &payload);
+    let trusted = keys::all_keys()?;
+    let mut signer = None;
+    for s in &envelope.signatures {
+        let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+        if trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &signed, &sig))
+            && keys::key_problem(&s.keyid, Some(translog::judged_at(logged, &s.keyid)))?.is_none()
+        {
+            signer = Some(s.keyid.clone());
+            break;
+        }
+    }
+    let signer = signer.ok_or("Provenance has no valid signature from a trusted key")?;
+
+    let sha256 = hex::encode(Sha256::digest(pkg_data));
+    if !statement.subject.iter().any(|s| s.digest.get("sha256") == Some(&sha256)) {
+        return Err("Provenance subject does not match the package hash".into());
+    }
+    Ok((statement, signer))
+}
+
+// refuse a package whose signed manifest says its build recorded provenance when none is next to it
+pub fn require(pkg_path: &Path, manifest: Option<&Manifest>) -> Result<(), Box<dyn std::error::Error>> {
+    if manifest.is_some_and(|m| m.provenance) && !pkg_path.with_extension(PROVENANCE_EXTENSION).exists() {
+        return Err("Package was built with provenance, but its .intoto is missing".into());
+    }
+    Ok(())
+}
+
+// check the provenance stored next to a package, None for packages built without one
+pub fn check(pkg_path: &Path, pkg_data: &[u8]) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let path = pkg_path.with_extension(PROVENANCE_EXTENSION);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let logged = translog::logged(pkg_path, pkg_data).ok().flatten();
+    let (statement, signer) = verify(&fs::read(&path)?, pkg_data, logged.as_ref())?;
+
+    let definition = &statement.predicate.build_definition;
+    let source = definition.resolved_dependencies.first()
+        .and_then(|s| s.uri.clone())
+        .unwrap_or_else(|| definition.external_parameters.source.clone());
+    let dirty = definition.resolved_dependencies.first()
+        .is_some_and(|s| s.annotations.get("dirty").is_some_and(|d| d == "true"));
+    let run = &statement.predicate.run_details;
+    Ok(Some(format!(
+        "built from {source}{} by {} at {}, {} input files, signed by {signer}",
+        if dirty { " (uncommitted changes)" } else { "" },
+        run.builder.id,
+        run.metadata.finished_on,
+        definition.resolved_dependencies.len().saturating_sub(1),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    #[test]
+    fn stripped_provenance_is_refused() {
+        testutil::home();
+        let dir = testutil::temp_dir("provenance");
+        fs::write(dir.join("hello.txt"), "hello").unwrap();
+        let pkg_path = dir.join("prov-1.0.0.pkg");
+        fs::write(&pkg_path, b"package").unwrap();
+
+        let manifest = Manifest { name: "prov".into(), version: "1.0.0".into(), provenance: true, ..Default::default() };
+        let err = require(&pkg_path, Some(&manifest)).unwrap_err().to_string();
+        assert!(err.contains(".intoto"), "{err}");
+        // built before provenance was recorded
+        require(&pkg_path, Some(&Manifest::default())).unwrap();
+        require(&pkg_path, None).unwrap();
+
+        let envelope = record(&manifest, "tar.zst", &dir, None, "prov-1.0.0.pkg", b"package", keys::signing_time()).unwrap();
+        fs::write(pkg_path.with_extension(PROVENANCE_EXTENSION), envelope).unwrap();
+        require(&pkg_path, Some(&manifest)).unwrap();
+        let report = check(&pkg_path, b"package").unwrap().unwrap();
+        assert!(report.contains("signed by"), "{report}");
+    }
+}
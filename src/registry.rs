@@ -0,0 +1,64 @@
+use std::{fs, path::Path};
+use base64::{engine::general_purpose, Engine};
+use reqwest::header;
+use crate::{config::Registry, manifest::Manifest, package::open_pkg, provenance::PROVENANCE_EXTENSION, repo::ManifestSidecar, server::{UploadRequest, UploadResponse}, status::PackageStatus};
+
+// upload a signed package, its manifest and provenance to a registry started with `serve --writable`
+pub async fn upload_pkg(registry: &Registry, name: &str, version: &str, pkg_path: &Path) -> Result<UploadResponse, Box<dyn std::error::Error>> {
+    let token = registry.token.as_deref().ok_or_else(|| format!("Registry {} has no token configured", registry.name))?;
+
+    let package = fs::read(pkg_path)?;
+    let signature = fs::read(pkg_path.with_extension("sig")).map_err(|e| format!("Signature file not found: {e}"))?;
+    // the registry cannot decrypt the package, so the manifest travels next to it, signed for this package
+    let (_, entries) = open_pkg(name, pkg_path)?;
+    let manifest = match Manifest::from_entries(&entries)? {
+        Some(manifest) => Some(ManifestSidecar::sign(manifest, &package)?),
+        None => None,
+    };
+
+    let provenance = fs::read(pkg_path.with_extension(PROVENANCE_EXTENSION)).ok();
+
+    let body = UploadRequest {
+        package: general_purpose::STANDARD.encode(&package),
+        signature: general_purpose::STANDARD.encode(&signature),
+        manifest,
+        provenance: provenance.map(|data| general_purpose::STANDARD.encode(data)),
+    };
+
+    let url = format!("{}/api/packages/{name}/{version}", registry.url.trim_end_matches('/'));
+    let resp = reqwest::Client::new()
+        .put(&url)
+        .bearer_auth(token)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&body)?)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let reason = resp.text().await.unwrap_or_default();
+        return Err(format!("PUT {url} failed: {status} {reason}").into());
+    }
+    Ok(serde_json::from_slice(&resp.bytes().await?)?)
+}
+
+// replace the yank and deprecation flags of a version on a registry, needs the yank scope
+pub async fn set_status(registry: &Registry, name: &str, version: &str, status: &PackageStatus) -> Result<PackageStatus, Box<dyn std::error::Error>> {
+    let token = registry.token.as_deref().ok_or_else(|| format!("Registry {} has no token configured", registry.name))?;
+
+    let url = format!("{}/api/packages/{name}/{version}/status", registry.url.trim_end_matches('/'));
+    let resp = reqwest::Client::new()
+        .put(&url)
+        .bearer_auth(token)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(status)?)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let reason = resp.text().await.unwrap_or_default();
+        return Err(format!("PUT {url} failed: {status} {reason}").into());
+    }
+    Ok(serde_json::from_slice(&resp.bytes().await?)?)
+}
@@ -0,0 +1,438 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use rsa::{RsaPrivateKey, pkcs1::DecodeRsaPrivateKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{archive, config::{Config, Repository}, fetch, keys, manifest::Manifest, package::decrypt_pkg, provenance::PROVENANCE_EXTENSION, signatures::SignatureSet, status::PackageStatus, storage, translog::{LOG_FILE, PROOF_EXTENSION}, tuf};
+
+pub const INDEX_FILE: &str = "index.json";
+// extension of the manifest uploaded next to a package
+pub const MANIFEST_SIDECAR: &str = "manifest.json";
+
+// manifest uploaded next to a package a registry cannot decrypt, bound to the package hash
+// signed by a key that also signs the package, so a token alone cannot change index dependencies
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestSidecar {
+    pub sha256: String,
+    pub manifest: Manifest,
+}
+
+impl ManifestSidecar {
+    pub fn sign(manifest: Manifest, pkg_data: &[u8]) -> Result<tuf::Signed<Self>, Box<dyn std::error::Error>> {
+        let sidecar = ManifestSidecar { sha256: hex::encode(Sha256::digest(pkg_data)), manifest };
+        let signature = IndexSignature {
+            keyid: keys::key_id(&keys::load_public_key()?)?,
+            sig: general_purpose::STANDARD.encode(keys::sign_bytes(&serde_json::to_vec(&sidecar)?)?),
+            signed_at: None,
+        };
+        Ok(tuf::Signed { signed: sidecar, signatures: vec![signature] })
+    }
+
+    // manifest of a sidecar made for this package and signed by one of its trusted signers
+    pub fn verify(signed: tuf::Signed<Self>, pkg_data: &[u8], signers: &[String]) -> Result<Manifest, Box<dyn std::error::Error>> {
+        if signed.signed.sha256 != hex::encode(Sha256::digest(pkg_data)) {
+            return Err("Manifest was signed for another package".into());
+        }
+        let payload = serde_json::to_vec(&signed.signed)?;
+        let trusted = keys::trusted_keys()?;
+        let valid = signed.signatures.iter().any(|s| {
+            let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+            signers.contains(&s.keyid) && trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &payload, &sig))
+        });
+        if !valid {
+            return Err("Manifest is not signed by a signer of the package".into());
+        }
+        Ok(signed.signed.manifest)
+    }
+}
+
+// package listed in a repository index
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    pub file: String,
+    pub sha256: String,
+    pub size: u64,
+    pub signer: Option<String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub status: PackageStatus,
+}
+
+impl IndexEntry {
+    // describe a package file, file is its path relative to the repository root
+    pub fn from_pkg(name: &str, version: &str, file: String, pkg_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let pkg_data = fs::read(pkg_path)?;
+        // first trusted co-signer, install applies the full signature policy
+        let signer = SignatureSet::read(pkg_path).anchored(pkg_path, &pkg_data).trusted_signers(&pkg_data).ok()
+            .and_then(|signers| signers.into_iter().next());
+
+        Ok(IndexEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            file,
+            sha256: hex::encode(Sha256::digest(&pkg_data)),
+            size: pkg_data.len() as u64,
+            signer,
+            dependencies: read_dependencies(pkg_path, &pkg_data).unwrap_or_default(),
+            status: PackageStatus::default(),
+        })
+    }
+}
+
+// signed part of index.json
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Index {
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub packages: Vec<IndexEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexSignature {
+    pub keyid: String,
+    pub sig: String,
+    // package signatures cover this time too, metadata signatures leave it out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_at: Option<DateTime<Utc>>,
+}
+
+// index.json on disk: index plus detached signatures over its JSON encoding
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedIndex {
+    pub signed: Index,
+    pub signatures: Vec<IndexSignature>,
+}
+
+impl Index {
+    // verify index.json in dir against trusted keys
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = dir.join(INDEX_FILE);
+        let data = fs::read(&path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+        Index::verify(&data, &path.display().to_string())
+    }
+
+    // parse index.json content, keeping it only if a trusted key signed it
+    pub fn verify(data: &[u8], origin: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let signed_index: SignedIndex = serde_json::from_slice(data)?;
+
+        let payload = serde_json::to_vec(&signed_index.signed)?;
+        let trusted = keys::trusted_keys()?;
+        let valid = signed_index.signatures.iter().any(|s| {
+            let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+            trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &payload, &sig))
+        });
+
+        if !valid {
+            return Err(format!("Index {origin} has no valid signature from a trusted key").into());
+        }
+        Ok(signed_index.signed)
+    }
+
+    // sort entries and sign with the local key
+    pub fn sign(&mut self) -> Result<SignedIndex, Box<dyn std::error::Error>> {
+        self.packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        self.updated_at = Some(Utc::now());
+
+        let payload = serde_json::to_vec(self)?;
+        let signature = IndexSignature {
+            keyid: keys::key_id(&keys::load_public_key()?)?,
+            sig: general_purpose::STANDARD.encode(keys::sign_bytes(&payload)?),
+            signed_at: None,
+        };
+        Ok(SignedIndex { signed: self.clone(), signatures: vec![signature] })
+    }
+
+    // sign and write index.json atomically
+    pub fn save(&mut self, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let signed_index = self.sign()?;
+
+        let tmp_path = dir.join(format!("{INDEX_FILE}.tmp"));
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&signed_index)?)?;
+        fs::rename(&tmp_path, dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str, version: &str) -> Option<&IndexEntry> {
+        self.packages.iter().find(|e| e.name == name && e.version == version)
+    }
+}
+
+// add or replace a package in the index of an export dir
+pub fn update_index(dir: &Path, name: &str, version: &str, pkg_path: &Path, status: PackageStatus) -> Result<(), Box<dyn std::error::Error>> {
+    // existing index is only kept if it still verifies
+    let mut index = if dir.join(INDEX_FILE).exists() {
+        Index::load(dir)?
+    } else {
+        Index::default()
+    };
+
+    let file = pkg_path.file_name().unwrap().to_string_lossy().to_string();
+    let mut entry = IndexEntry::from_pkg(name, version, file, pkg_path)?;
+    entry.status = status;
+
+    index.packages.retain(|e| !(e.name == name && e.version == version));
+    index.packages.push(entry);
+    index.save(dir)?;
+    tuf::update(dir, &index.packages, &tuf::Lifetimes::export()?)?;
+
+    println!("🗂️ Index updated: {}", dir.join(INDEX_FILE).display());
+    Ok(())
+}
+
+// change the yank and deprecation flags of a listed package, false when the index does not list it
+pub fn set_status(dir: &Path, name: &str, version: &str, status: &PackageStatus) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut index = Index::load(dir)?;
+    let Some(entry) = index.packages.iter_mut().find(|e| e.name == name && e.version == version) else {
+        return Ok(false);
+    };
+    entry.status = status.clone();
+    index.save(dir)?;
+    tuf::update(dir, &index.packages, &tuf::Lifetimes::export()?)?;
+
+    println!("🗂️ Index updated: {}", dir.join(INDEX_FILE).display());
+    Ok(true)
+}
+
+// re-sign the TUF metadata of an export dir before its timestamp expires ([tuf] in config.toml, 30 days by default)
+pub fn refresh(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let index = Index::load(dir)?;
+    tuf::update(dir, &index.packages, &tuf::Lifetimes::export()?)?;
+    println!("🕒 Metadata of {} is fresh", dir.display());
+    Ok(())
+}
+
+// move the root of an export dir from the key in old_key_path to the local key
+pub fn rotate_root(dir: &Path, old_key_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let pem = fs::read_to_string(old_key_path).map_err(|e| format!("Cannot read private key {}: {e}", old_key_path.display()))?;
+    let old_key = RsaPrivateKey::from_pkcs1_pem(&pem)?;
+    let mut index = Index::load(dir)?;
+    tuf::rotate_root(dir, &index.packages, &old_key, &tuf::Lifetimes::export()?)?;
+    index.save(dir)?;
+    println!("🔑 Root of {} handed over to the local key", dir.display());
+    Ok(())
+}
+
+// dependencies from the embedded manifest, needs the local secret key
+// falls back to the signed manifest uploaded next to the package on a registry
+fn read_dependencies(pkg_path: &Path, pkg_data: &[u8]) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let embedded = fs::read(storage::get_key_path()).ok()
+        .and_then(|key_bytes| decrypt_pkg(pkg_data, &key_bytes).ok())
+        .and_then(|(format, archive_data)| archive::read_entries(&archive_data, format).ok());
+
+    let manifest = match embedded {
+        Some(entries) => Manifest::from_entries(&entries)?,
+        None => {
+            let sidecar = pkg_path.with_extension(MANIFEST_SIDECAR);
+            match fs::read(sidecar) {
+                Ok(data) => {
+                    let signers = SignatureSet::read(pkg_path).anchored(pkg_path, pkg_data).trusted_signers(pkg_data)?;
+                    Some(ManifestSidecar::verify(serde_json::from_slice(&data)?, pkg_data, &signers)?)
+                }
+                Err(_) => None,
+            }
+        }
+    };
+    Ok(manifest.map(|m| m.dependencies).unwrap_or_default())
+}
+
+// where a repository lives: local dir (plain path or file://) or http(s) url
+pub enum RepoSource {
+    Local(PathBuf),
+    Http(String),
+}
+
+impl RepoSource {
+    pub fn parse(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(path) = url.strip_prefix("file://") {
+            Ok(RepoSource::Local(PathBuf::from(path)))
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            Ok(RepoSource::Http(url.trim_end_matches('/').to_string()))
+        } else if url.contains("://") {
+            Err(format!("Unsupported repository url: {url}").into())
+        } else {
+            Ok(RepoSource::Local(PathBuf::from(url)))
+        }
+    }
+
+    // file at the repository root, None if it does not exist
+    pub async fn read(&self, file: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self {
+            RepoSource::Local(dir) => match fs::read(dir.join(file)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            RepoSource::Http(base) => fetch::fetch_optional(&format!("{base}/{file}")).await,
+        }
+    }
+
+    // packages from the TUF targets role, plain index.json for repositories without metadata
+    async fn load_index(&self, repo_name: &str) -> Result<Index, Box<dyn std::error::Error>> {
+        if let Some(targets) = tuf::refresh_targets(repo_name, self).await? {
+            return Ok(Index { updated_at: None, packages: targets.targets });
+        }
+
+        eprintln!("⚠️ Repository {repo_name} has no TUF metadata, rollback protection is off");
+        match self {
+            RepoSource::Local(dir) => Index::load(dir),
+            RepoSource::Http(base) => {
+                let url = format!("{base}/{INDEX_FILE}");
+                Index::verify(&fetch::fetch_bytes(&url).await?, &url)
+            }
+        }
+    }
+}
+
+// configured repository with its verified index
+pub struct LoadedRepo {
+    pub repo: Repository,
+    pub source: RepoSource,
+    pub index: Index,
+}
+
+impl LoadedRepo {
+    // local path of an index entry once its content matches the index hash
+    // remote packages are downloaded into the cache first
+    pub async fn verified_path(&self, entry: &IndexEntry) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let file = archive::safe_path(&entry.file)?;
+
+        match &self.source {
+            RepoSource::Local(dir) => {
+                let pkg_path = dir.join(file);
+                let pkg_data = fs::read(&pkg_path)?;
+                if hex::encode(Sha256::digest(&pkg_data)) != entry.sha256 {
+                    return Err(format!("{} in repository {} does not match the index hash", entry.file, self.repo.name).into());
+                }
+                Ok(pkg_path)
+            }
+            RepoSource::Http(base) => {
+                let url = format!("{base}/{}", entry.file);
+                let pkg_path = fetch::fetch_cached(&url, &entry.sha256).await?;
+
+                // signature is checked against trusted keys on install
+                // always fetched again, co-signers may have been added since
+                let sig_url = format!("{base}/{}", file.with_extension("sig").to_string_lossy());
+                fs::write(pkg_path.with_extension("sig"), fetch::fetch_bytes(&sig_url).await?)?;
+
+                // inclusion proof, absent for packages published before the log
+                // the current log is fetched either way, a repository with one requires the proof
+                let proof_url = format!("{base}/{}", file.with_extension(PROOF_EXTENSION).to_string_lossy());
+                let proof_path = pkg_path.with_extension(PROOF_EXTENSION);
+                match fetch::fetch_optional(&proof_url).await? {
+                    Some(data) => fs::write(&proof_path, data)?,
+                    None if proof_path.exists() => fs::remove_file(&proof_path)?,
+                    None => {}
+                }
+                let log_path = pkg_path.with_extension(LOG_FILE);
+                match fetch::fetch_optional(&format!("{base}/{LOG_FILE}")).await? {
+                    Some(log) => fs::write(&log_path, log)?,
+                    None if log_path.exists() => fs::remove_file(&log_path)?,
+                    None => {}
+                }
+
+                // build provenance, absent for packages built before it was recorded
+                let provenance_url = format!("{base}/{}", file.with_extension(PROVENANCE_EXTENSION).to_string_lossy());
+                let provenance_path = pkg_path.with_extension(PROVENANCE_EXTENSION);
+                match fetch::fetch_optional(&provenance_url).await? {
+                    Some(data) => fs::write(&provenance_path, data)?,
+                    None if provenance_path.exists() => fs::remove_file(&provenance_path)?,
+                    None => {}
+                }
+                Ok(pkg_path)
+            }
+        }
+    }
+}
+
+// verified indexes of every configured repository, unreachable ones are skipped
+// metadata that fails verification (expired, rolled back) stops the command instead
+pub async fn load_repositories() -> Result<Vec<LoadedRepo>, Box<dyn std::error::Error>> {
+    let mut repos = Vec::new();
+    for repo in Config::load()?.repositories {
+        let loaded = match RepoSource::parse(&repo.url) {
+            Ok(source) => source.load_index(&repo.name).await.map(|index| (source, index)),
+            Err(e) => Err(e),
+        };
+        match loaded {
+            Ok((source, index)) => repos.push(LoadedRepo { repo, source, index }),
+            Err(e) if e.is::<tuf::MetadataError>() => return Err(format!("Repository {}: {e}", repo.name).into()),
+            Err(e) => eprintln!("⚠️ Skipping repository {}: {e}", repo.name),
+        }
+    }
+    Ok(repos)
+}
+
+// find a package in configured repositories, checking its hash against the index
+pub async fn locate_in_repos(name: &str, version: &str) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    for loaded in load_repositories().await? {
+        if let Some(entry) = loaded.index.find(name, version) {
+            return Ok(Some(loaded.verified_path(entry).await?));
+        }
+    }
+    Ok(None)
+}
+
+// print index entries whose name contains query
+pub async fn search(query: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut found = 0;
+    for loaded in load_repositories().await? {
+        for entry in loaded.index.packages.iter().filter(|e| e.name.contains(query)) {
+            println!(
+                "- {} {} [{}] {} bytes, signer {}{}",
+                entry.name, entry.version, loaded.repo.name, entry.size, entry.signer.as_deref().unwrap_or("none"), entry.status.tags()
+            );
+            found += 1;
+        }
+    }
+
+    if found == 0 {
+        println!("🔎 No packages matching '{query}'");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn index(version: &str) -> Index {
+        let entry = IndexEntry {
+            name: "repo-signed".into(),
+            version: version.into(),
+            file: format!("repo-signed-{version}.pkg"),
+            sha256: "00".repeat(32),
+            size: 1,
+            signer: None,
+            dependencies: BTreeMap::new(),
+            status: PackageStatus::default(),
+        };
+        Index { updated_at: None, packages: vec![entry] }
+    }
+
+    #[test]
+    fn only_an_untouched_index_signed_by_a_trusted_key_is_accepted() {
+        testutil::home();
+        let signed = index("1.0.0").sign().unwrap();
+        let data = serde_json::to_vec(&signed).unwrap();
+        assert_eq!(Index::verify(&data, "test").unwrap().packages[0].version, "1.0.0");
+
+        let mut tampered = signed.clone();
+        tampered.signed.packages[0].sha256 = "ff".repeat(32);
+        assert!(Index::verify(&serde_json::to_vec(&tampered).unwrap(), "test").is_err());
+
+        // a valid signature of the same key over another index
+        let mut wrong = signed.clone();
+        wrong.signatures = index("2.0.0").sign().unwrap().signatures;
+        assert!(Index::verify(&serde_json::to_vec(&wrong).unwrap(), "test").is_err());
+
+        let mut unknown = signed.clone();
+        unknown.signatures[0].keyid = "0000000000000000".into();
+        let err = Index::verify(&serde_json::to_vec(&unknown).unwrap(), "test").unwrap_err().to_string();
+        assert_eq!(err, "Index test has no valid signature from a trusted key");
+    }
+}
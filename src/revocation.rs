@@ -0,0 +1,87 @@
+use std::{fmt, fs, path::Path};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rsa::{pkcs1::EncodeRsaPublicKey, pkcs8::LineEnding};
+use serde::{Deserialize, Serialize};
+use crate::{keys, storage, tuf::Signed};
+
+// why a key was revoked, compromised keys lose every signature, others only later ones
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RevocationReason {
+    Compromised,
+    Superseded,
+    Retired,
+}
+
+impl fmt::Display for RevocationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RevocationReason::Compromised => "compromised",
+            RevocationReason::Superseded => "superseded",
+            RevocationReason::Retired => "retired",
+        })
+    }
+}
+
+// revocation certificate, signed by the revoked key so anyone holding it can check it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Revocation {
+    pub keyid: String,
+    pub public_key: String,
+    pub reason: RevocationReason,
+    pub revoked_at: DateTime<Utc>,
+}
+
+// revoke the local key, returns the distributable certificate
+pub fn revoke_local(reason: RevocationReason) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let key = keys::load_public_key()?;
+    let keyid = keys::key_id(&key)?;
+    if load(&keyid)?.is_some() {
+        return Err(format!("Key {keyid} is already revoked").into());
+    }
+
+    let revocation = Revocation {
+        keyid: keyid.clone(),
+        public_key: key.to_pkcs1_pem(LineEnding::LF)?,
+        reason,
+        revoked_at: keys::signing_time(),
+    };
+    let data = serde_json::to_vec_pretty(&keys::self_sign(revocation)?)?;
+    save(&keyid, &data)?;
+    Ok(data)
+}
+
+// import a revocation certificate from another publisher, returns the revoked key id
+pub fn import(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    let signed: Signed<Revocation> = serde_json::from_slice(&data).map_err(|e| format!("Invalid revocation certificate: {e}"))?;
+    let revocation = &signed.signed;
+    keys::verify_self_signed(&signed, &revocation.public_key, &revocation.keyid)?;
+
+    // keep the stricter one: compromised, then the earliest time
+    if let Some(existing) = load(&revocation.keyid)?
+        && (existing.reason == RevocationReason::Compromised
+            || (revocation.reason != RevocationReason::Compromised && existing.revoked_at <= revocation.revoked_at))
+    {
+        return Ok(revocation.keyid.clone());
+    }
+    save(&revocation.keyid, &data)?;
+    Ok(revocation.keyid.clone())
+}
+
+// revocation recorded for a key id
+pub fn load(keyid: &str) -> Result<Option<Revocation>, Box<dyn std::error::Error>> {
+    let path = storage::get_revoked_dir().join(format!("{keyid}.json"));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let signed: Signed<Revocation> = serde_json::from_slice(&fs::read(&path)?)?;
+    Ok(Some(signed.signed))
+}
+
+fn save(keyid: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(storage::get_revoked_dir())?;
+    fs::write(storage::get_revoked_dir().join(format!("{keyid}.json")), data)?;
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::{fmt, fs, path::{Path, PathBuf}};
+use chrono::Utc;
+use clap::ValueEnum;
+use rand::Rng;
+use serde_json::{json, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use crate::{archive::{self, ArchiveEntry}, blob, manifest::{is_meta, Manifest, META_DIR}, package::{decrypt_pkg, locate_pkg, open_pkg}, storage};
+
+// software bill of materials formats, both are embedded by build
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SbomFormat {
+    Cyclonedx,
+    Spdx,
+}
+
+pub const FORMATS: [SbomFormat; 2] = [SbomFormat::Cyclonedx, SbomFormat::Spdx];
+
+impl SbomFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SbomFormat::Cyclonedx => "cdx.json",
+            SbomFormat::Spdx => "spdx.json",
+        }
+    }
+
+    // path inside the archive
+    pub fn entry(&self) -> String {
+        format!("{META_DIR}/sbom.{}", self.extension())
+    }
+
+    // SBOM document of a package
+    pub fn document(&self, manifest: &Manifest, files: &[SbomFile]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let document = match self {
+            SbomFormat::Cyclonedx => cyclonedx(manifest, files),
+            SbomFormat::Spdx => spdx(manifest, files),
+        };
+        Ok(serde_json::to_vec_pretty(&document)?)
+    }
+
+    // copy saved at install time
+    pub fn installed_path(&self, name: &str, version: &str) -> PathBuf {
+        storage::get_sbom_dir().join(format!("{name}-{version}.{}", self.extension()))
+    }
+}
+
+impl fmt::Display for SbomFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SbomFormat::Cyclonedx => "CycloneDX",
+            SbomFormat::Spdx => "SPDX",
+        })
+    }
+}
+
+// payload file as listed in SBOMs, SPDX requires SHA-1
+pub struct SbomFile {
+    pub path: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl SbomFile {
+    pub fn new(path: &str, data: &[u8]) -> Self {
+        SbomFile {
+            path: path.to_string(),
+            sha1: hex::encode(Sha1::digest(data)),
+            sha256: hex::encode(Sha256::digest(data)),
+        }
+    }
+}
+
+// SBOM archive entries for build, files are read from the source dir of the manifest
+pub fn to_entries(manifest: &Manifest, src_dir: &Path) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for file in &manifest.files {
+        files.push(SbomFile::new(&file.path, &fs::read(src_dir.join(&file.path))?));
+    }
+    let mut entries = Vec::new();
+    for format in FORMATS {
+        entries.push(ArchiveEntry { path: format.entry(), mode: 0o644, is_dir: false, data: format.document(manifest, &files)? });
+    }
+    Ok(entries)
+}
+
+// embedded SBOM of a package, generated from its signed manifest and files for packages built without one
+// returns the document and whether it was generated
+pub fn from_entries(entries: &[ArchiveEntry], name: &str, version: &str, format: SbomFormat) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
+    if let Some(entry) = entries.iter().find(|e| e.path == format.entry()) {
+        return Ok((entry.data.clone(), false));
+    }
+
+    let mut manifest = Manifest::from_entries(entries)?.unwrap_or_default();
+    manifest.name = name.to_string();
+    manifest.version = version.to_string();
+    let files: Vec<SbomFile> = entries.iter()
+        .filter(|e| !e.is_dir && !is_meta(&e.path))
+        .map(|e| SbomFile::new(&e.path, &e.data))
+        .collect();
+    Ok((format.document(&manifest, &files)?, true))
+}
+
+// keep every SBOM of an installed package, sbom works without the package afterwards
+pub fn save_installed(entries: &[ArchiveEntry], name: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(storage::get_sbom_dir())?;
+    for format in FORMATS {
+        let (data, _) = from_entries(entries, name, version, format)?;
+        fs::write(format.installed_path(name, version), data)?;
+    }
+    Ok(())
+}
+
+pub fn remove_installed(name: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for format in FORMATS {
+        let path = format.installed_path(name, version);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// SBOM of an installed version, a package file, a package of the storage backend or of a repository
+pub async fn export(name: &str, version: &str, from_file: Option<&Path>, format: SbomFormat) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let installed = format.installed_path(name, version);
+    if from_file.is_none() && installed.exists() {
+        return Ok(fs::read(installed)?);
+    }
+
+    // own builds are read even before they are signed, the secret key authenticates them
+    let store = blob::open()?;
+    let stored = match from_file {
+        Some(_) => None,
+        None => blob::pkg_path(store.as_ref(), &blob::pkg_key(name, version)).await?,
+    };
+    let entries = match stored {
+        Some(pkg_path) => {
+            let (format, decrypted_data) = decrypt_pkg(&fs::read(pkg_path)?, &fs::read(storage::get_key_path())?)?;
+            archive::read_entries(&decrypted_data, format)?
+        }
+        None => open_pkg(name, &locate_pkg(name, version, from_file).await?)?.1,
+    };
+
+    let (data, generated) = from_entries(&entries, name, version, format)?;
+    if generated {
+        eprintln!("⚠️ {name} {version} was built without an SBOM, generated one from its signed manifest");
+    }
+    Ok(data)
+}
+
+fn purl(name: &str, version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("pkg:generic/{name}@{version}"),
+        None => format!("pkg:generic/{name}"),
+    }
+}
+
+// random version 4 uuid
+fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rng().fill(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// CycloneDX 1.5 JSON
+fn cyclonedx(manifest: &Manifest, files: &[SbomFile]) -> Value {
+    let root = purl(&manifest.name, Some(&manifest.version));
+    let mut component = json!({
+        "type": "application",
+        "bom-ref": root,
+        "name": manifest.name,
+        "version": manifest.version,
+        "purl": root,
+    });
+    if let Some(author) = &manifest.author {
+        component["author"] = json!(author);
+    }
+    if let Some(description) = &manifest.description {
+        component["description"] = json!(description);
+    }
+    if let Some(license) = &manifest.license {
+        component["licenses"] = json!([{ "expression": license }]);
+    }
+
+    let mut components: Vec<Value> = files.iter().map(|f| json!({
+        "type": "file",
+        "bom-ref": format!("file:{}", f.path),
+        "name": f.path,
+        "hashes": [
+            { "alg": "SHA-1", "content": f.sha1 },
+            { "alg": "SHA-256", "content": f.sha256 },
+        ],
+    })).collect();
+    for (dep, req) in &manifest.dependencies {
+        components.push(json!({
+            "type": "library",
+            "bom-ref": purl(dep, None),
+            "name": dep,
+            "purl": purl(dep, None),
+            "properties": [{ "name": "securepkg:requirement", "value": req }],
+        }));
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid()),
+        "version": 1,
+        "metadata": {
+            "timestamp": Utc::now().to_rfc3339(),
+            "tools": { "components": [{ "type": "application", "name": "securepkg", "version": env!("CARGO_PKG_VERSION") }] },
+            "component": component,
+        },
+        "components": components,
+        "dependencies": [{
+            "ref": root,
+            "dependsOn": manifest.dependencies.keys().map(|dep| purl(dep, None)).collect::<Vec<_>>(),
+        }],
+    })
+}
+
+// SPDX 2.3 JSON
+fn spdx(manifest: &Manifest, files: &[SbomFile]) -> Value {
+    // verification code: SHA-1 of the sorted file SHA-1s
+    let mut sha1s: Vec<&str> = files.iter().map(|f| f.sha1.as_str()).collect();
+    sha1s.sort();
+    let verification_code = hex::encode(Sha1::digest(sha1s.concat().as_bytes()));
+
+    let mut package = json!({
+        "SPDXID": "SPDXRef-Package",
+        "name": manifest.name,
+        "versionInfo": manifest.version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": true,
+        "packageVerificationCode": { "packageVerificationCodeValue": verification_code },
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": manifest.license.as_deref().unwrap_or("NOASSERTION"),
+        "copyrightText": "NOASSERTION",
+        "externalRefs": [{ "referenceCategory": "PACKAGE-MANAGER", "referenceType": "purl", "referenceLocator": purl(&manifest.name, Some(&manifest.version)) }],
+    });
+    if let Some(author) = &manifest.author {
+        package["supplier"] = json!(format!("Person: {author}"));
+    }
+    if let Some(description) = &manifest.description {
+        package["description"] = json!(description);
+    }
+
+    let mut packages = vec![package];
+    let mut relationships = vec![json!({ "spdxElementId": "SPDXRef-DOCUMENT", "relationshipType": "DESCRIBES", "relatedSpdxElement": "SPDXRef-Package" })];
+    for (i, (dep, req)) in manifest.dependencies.iter().enumerate() {
+        let id = format!("SPDXRef-Dependency-{i}");
+        packages.push(json!({
+            "SPDXID": id,
+            "name": dep,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+            "comment": format!("version requirement {req}"),
+        }));
+        relationships.push(json!({ "spdxElementId": "SPDXRef-Package", "relationshipType": "DEPENDS_ON", "relatedSpdxElement": id }));
+    }
+
+    let spdx_files: Vec<Value> = files.iter().enumerate().map(|(i, f)| {
+        let id = format!("SPDXRef-File-{i}");
+        relationships.push(json!({ "spdxElementId": "SPDXRef-Package", "relationshipType": "CONTAINS", "relatedSpdxElement": id }));
+        json!({
+            "SPDXID": id,
+            "fileName": format!("./{}", f.path),
+            "checksums": [
+                { "algorithm": "SHA1", "checksumValue": f.sha1 },
+                { "algorithm": "SHA256", "checksumValue": f.sha256 },
+            ],
+            "licenseConcluded": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+        })
+    }).collect();
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", manifest.name, manifest.version),
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}-{}", manifest.name, manifest.version, uuid()),
+        "creationInfo": {
+            "created": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: securepkg-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": spdx_files,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::testutil::temp_dir;
+
+    #[test]
+    fn documents_list_the_manifest_files_and_dependencies() {
+        let src = temp_dir("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/run.sh"), "echo run").unwrap();
+        fs::write(src.join("readme.txt"), "hello").unwrap();
+        fs::write(src.join("securepkg.toml"), "[dependencies]\nlibfoo = \"^1.2\"\nlibbar = \">=0.3\"\n").unwrap();
+        let manifest = Manifest::load(&src, "sbom-pkg", "1.0.0", None).unwrap();
+        let expected: BTreeMap<String, String> = manifest.files.iter().map(|f| (f.path.clone(), f.sha256.clone())).collect();
+        assert!(expected.contains_key("bin/run.sh") && expected.contains_key("readme.txt"));
+
+        let entries = to_entries(&manifest, &src).unwrap();
+        let document = |format: SbomFormat| -> Value {
+            let entry = entries.iter().find(|e| e.path == format.entry()).unwrap();
+            serde_json::from_slice(&entry.data).unwrap()
+        };
+
+        let cdx = document(SbomFormat::Cyclonedx);
+        let components = cdx["components"].as_array().unwrap();
+        let files: BTreeMap<String, String> = components.iter().filter(|c| c["type"] == "file").map(|c| {
+            let sha256 = c["hashes"].as_array().unwrap().iter().find(|h| h["alg"] == "SHA-256").unwrap()["content"].as_str().unwrap();
+            (c["name"].as_str().unwrap().to_string(), sha256.to_string())
+        }).collect();
+        assert_eq!(files, expected);
+        let libraries: Vec<(&str, &str)> = components.iter().filter(|c| c["type"] == "library")
+            .map(|c| (c["name"].as_str().unwrap(), c["properties"][0]["value"].as_str().unwrap()))
+            .collect();
+        assert_eq!(libraries, [("libbar", ">=0.3"), ("libfoo", "^1.2")]);
+        assert_eq!(cdx["dependencies"][0]["ref"], "pkg:generic/sbom-pkg@1.0.0");
+        assert_eq!(cdx["dependencies"][0]["dependsOn"], json!(["pkg:generic/libbar", "pkg:generic/libfoo"]));
+
+        let spdx = document(SbomFormat::Spdx);
+        let files: BTreeMap<String, String> = spdx["files"].as_array().unwrap().iter().map(|f| {
+            let sha256 = f["checksums"].as_array().unwrap().iter().find(|c| c["algorithm"] == "SHA256").unwrap()["checksumValue"].as_str().unwrap();
+            (f["fileName"].as_str().unwrap().trim_start_matches("./").to_string(), sha256.to_string())
+        }).collect();
+        assert_eq!(files, expected);
+        let packages: Vec<&str> = spdx["packages"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(packages, ["sbom-pkg", "libbar", "libfoo"]);
+        let relationships = spdx["relationships"].as_array().unwrap();
+        let count = |kind: &str| relationships.iter().filter(|r| r["relationshipType"] == kind).count();
+        assert_eq!((count("DEPENDS_ON"), count("CONTAINS")), (2, expected.len()));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{fmt, fs::{self, OpenOptions}, io::{IsTerminal, Write}, path::Path, process::Stdio, time::Duration};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use crate::{archive::ArchiveEntry, hooks::{exit_reason, log_tail, wait_or_kill}, manifest::Manifest, policy::InstallPolicy, storage};
+
+// limit of one install script in seconds
+pub const SCRIPT_TIMEOUT: u64 = 300;
+
+// [scripts] table of securepkg.toml, paths of package files run with sh in the install root
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct InstallScripts {
+    // in the verified staging dir, before it replaces the install dir
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_install: Option<String>,
+    // in the install dir, a failure rolls the install back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install: Option<String>,
+    // before the install dir is removed, a failure keeps it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_remove: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ScriptStage {
+    PreInstall,
+    PostInstall,
+    PreRemove,
+}
+
+impl fmt::Display for ScriptStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScriptStage::PreInstall => "pre-install",
+            ScriptStage::PostInstall => "post-install",
+            ScriptStage::PreRemove => "pre-remove",
+        })
+    }
+}
+
+impl InstallScripts {
+    pub fn is_empty(&self) -> bool {
+        self.pre_install.is_none() && self.post_install.is_none() && self.pre_remove.is_none()
+    }
+
+    pub fn get(&self, stage: ScriptStage) -> Option<&str> {
+        match stage {
+            ScriptStage::PreInstall => self.pre_install.as_deref(),
+            ScriptStage::PostInstall => self.post_install.as_deref(),
+            ScriptStage::PreRemove => self.pre_remove.as_deref(),
+        }
+    }
+
+    // declared scripts in run order
+    pub fn list(&self) -> Vec<(ScriptStage, &str)> {
+        [ScriptStage::PreInstall, ScriptStage::PostInstall, ScriptStage::PreRemove].into_iter()
+            .filter_map(|stage| self.get(stage).map(|path| (stage, path)))
+            .collect()
+    }
+}
+
+// show every script of a package and get consent from --allow-scripts, the install policy or the user
+// consent covers the pre-remove script too, it runs later without asking again
+pub fn consent(manifest: &Manifest, entries: &[ArchiveEntry], allow_scripts: bool) -> Result<(), Box<dyn std::error::Error>> {
+    println!("📜 {} {} has install scripts:", manifest.name, manifest.version);
+    for (stage, path) in manifest.scripts.list() {
+        let entry = entries.iter().find(|e| e.path == path).ok_or_else(|| format!("Install script {path} is not in the package"))?;
+        println!("--- {stage}: {path}");
+        println!("{}", String::from_utf8_lossy(&entry.data).trim_end());
+    }
+    println!("---");
+
+    if allow_scripts {
+        println!("✅ Install scripts allowed (--allow-scripts)");
+        return Ok(());
+    }
+    if InstallPolicy::load()?.is_some_and(|policy| policy.allow_scripts == Some(true)) {
+        println!("✅ Install scripts approved by the install policy");
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err("Install scripts need consent, rerun with --allow-scripts or set allow_scripts = true in the install policy".into());
+    }
+
+    print!("Run these scripts? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Err("Install scripts were not approved".into());
+    }
+    Ok(())
+}
+
+// run one script of a package sandboxed in root, output goes to ~/.securepkg/logs/<name>-<version>.install.log
+// allow_unsandboxed (--allow-unsandboxed) lets it run where landlock cannot confine its writes
+pub async fn run(manifest: &Manifest, stage: ScriptStage, root: &Path, allow_unsandboxed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = manifest.scripts.get(stage) else { return Ok(()) };
+    let (name, version) = (&manifest.name, &manifest.version);
+    let root = fs::canonicalize(root)?;
+    let script = root.join(path);
+    if !script.is_file() {
+        return Err(format!("{stage} script {path} is missing from {}", root.display()).into());
+    }
+
+    fs::create_dir_all(storage::get_logs_dir())?;
+    let log_path = storage::get_logs_dir().join(format!("{name}-{version}.install.log"));
+    let mut log = OpenOptions::new().create(true).append(true).open(&log_path)?;
+    writeln!(log, "$ sh {path}   # {stage}")?;
+
+    println!("📜 Running {stage} script {path} (sandboxed)");
+    let mut sh = Command::new("sh");
+    sh.arg(&script)
+        .current_dir(&root)
+        .env_clear()
+        .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+        .env("HOME", &root)
+        .env("TMPDIR", &root)
+        .env("SECUREPKG_NAME", name)
+        .env("SECUREPKG_VERSION", version)
+        .env("SECUREPKG_INSTALL_DIR", &root)
+        .env("SECUREPKG_SCRIPT", stage.to_string())
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .kill_on_drop(true);
+    let ruleset = sandbox(&mut sh, &root, allow_unsandboxed)?;
+    let mut child = sh.spawn().map_err(|e| format!("Cannot start the {stage} script sandbox: {e}"))?;
+    drop(ruleset);
+
+    let failure = |reason: String| format!("{stage} script of {name} {version} {reason}, full log in {}:\n{}", log_path.display(), log_tail(&log_path));
+    match wait_or_kill(&mut child, Duration::from_secs(SCRIPT_TIMEOUT)).await? {
+        None => Err(failure(format!("timed out after {SCRIPT_TIMEOUT}s")).into()),
+        Some(status) if !status.success() => Err(failure(exit_reason(status)).into()),
+        Some(_) => Ok(()),
+    }
+}
+
+// own process group, no new privileges, an empty network namespace and writes confined to root by landlock
+// returns the landlock ruleset, kept open until the child is spawned
+#[cfg(target_os = "linux")]
+fn sandbox(command: &mut Command, root: &Path, allow_unsandboxed: bool) -> Result<Option<OwnedFd>, Box<dyn std::error::Error>> {
+    use std::ffi::CString;
+
+    // everything the child writes is prepared here, allocating after fork is not safe
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let files = [
+        (CString::new("/proc/self/setgroups")?, b"deny".to_vec()),
+        (CString::new("/proc/self/uid_map")?, format!("{uid} {uid} 1").into_bytes()),
+        (CString::new("/proc/self/gid_map")?, format!("{gid} {gid} 1").into_bytes()),
+    ];
+    let ruleset = write_ruleset(root)?;
+    if ruleset.is_none() {
+        unconfined(root, allow_unsandboxed)?;
+    }
+    let ruleset_fd = ruleset.as_ref().map(|fd| fd.as_raw_fd());
+
+    command.process_group(0);
+    unsafe {
+        command.pre_exec(move || {
+            // root can create the network namespace directly, other users need their own user namespace
+            if libc::unshare(libc::CLONE_NEWNET) != 0 {
+                if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // keep the caller's ids inside the user namespace
+                for (path, data) in &files {
+                    let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+                    if fd < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    let written = libc::write(fd, data.as_ptr().cast(), data.len());
+                    libc::close(fd);
+                    if written < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+            }
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if let Some(fd) = ruleset_fd
+                && libc::syscall(libc::SYS_landlock_restrict_self, fd, 0) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(ruleset)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sandbox(_command: &mut Command, _root: &Path, _allow_unsandboxed: bool) -> Result<Option<()>, Box<dyn std::error::Error>> {
+    Err("Install scripts only run in the Linux sandbox".into())
+}
+
+// without landlock a script could write anywhere the user can, only --allow-unsandboxed or the install policy accept that
+pub fn unconfined(root: &Path, allow_unsandboxed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !(allow_unsandboxed || InstallPolicy::load()?.is_some_and(|policy| policy.allow_unsandboxed == Some(true))) {
+        return Err(format!(
+            "Landlock is not available, the script could write outside {}; rerun with --allow-unsandboxed or set allow_unsandboxed = true in the install policy",
+            root.display()
+        ).into());
+    }
+    println!("⚠️ Landlock is not available, the script can write outside {}", root.display());
+    Ok(())
+}
+
+// landlock filesystem rights, see linux/landlock.h
+#[cfg(target_os = "linux")]
+mod landlock {
+    pub const CREATE_RULESET_VERSION: u32 = 1;
+    pub const RULE_PATH_BENEATH: libc::c_int = 1;
+    pub const WRITE_FILE: u64 = 1 << 1;
+    pub const REMOVE_DIR: u64 = 1 << 4;
+    pub const REMOVE_FILE: u64 = 1 << 5;
+    pub const MAKE_CHAR: u64 = 1 << 6;
+    pub const MAKE_DIR: u64 = 1 << 7;
+    pub const MAKE_REG: u64 = 1 << 8;
+    pub const MAKE_SOCK: u64 = 1 << 9;
+    pub const MAKE_FIFO: u64 = 1 << 10;
+    pub const MAKE_BLOCK: u64 = 1 << 11;
+    pub const MAKE_SYM: u64 = 1 << 12;
+    pub const REFER: u64 = 1 << 13;
+    pub const TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    pub struct RulesetAttr {
+        pub handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    pub struct PathBeneathAttr {
+        pub allowed_access: u64,
+        pub parent_fd: i32,
+    }
+}
+
+// ruleset handling every write right the kernel knows, granted beneath root and on /dev/null only
+// reads and execution stay allowed everywhere, None when the kernel has no landlock
+#[cfg(target_os = "linux")]
+fn write_ruleset(root: &Path) -> Result<Option<OwnedFd>, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use landlock::*;
+
+    let abi = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<RulesetAttr>(), 0usize, CREATE_RULESET_VERSION) };
+    if abi < 1 {
+        return Ok(None);
+    }
+    let mut write = WRITE_FILE | REMOVE_DIR | REMOVE_FILE | MAKE_CHAR | MAKE_DIR | MAKE_REG | MAKE_SOCK | MAKE_FIFO | MAKE_BLOCK | MAKE_SYM;
+    if abi >= 2 {
+        write |= REFER;
+    }
+    if abi >= 3 {
+        write |= TRUNCATE;
+    }
+
+    let attr = RulesetAttr { handled_access_fs: write };
+    let fd = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, size_of::<RulesetAttr>(), 0) };
+    if fd < 0 {
+        return Err(format!("Cannot create the landlock ruleset: {}", std::io::Error::last_os_error()).into());
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+    for (path, access) in [(root, write), (Path::new("/dev/null"), write & (WRITE_FILE | TRUNCATE))] {
+        let file = fs::File::options().read(true).custom_flags(libc::O_PATH).open(path)?;
+        let rule = PathBeneathAttr { allowed_access: access, parent_fd: file.as_raw_fd() };
+        if unsafe { libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), RULE_PATH_BENEATH, &rule, 0) } != 0 {
+            return Err(format!("Cannot add landlock rule for {}: {}", path.display(), std::io::Error::last_os_error()).into());
+        }
+    }
+    Ok(Some(ruleset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{home, temp_dir};
+
+    #[test]
+    fn scripts_without_landlock_need_explicit_permission() {
+        home();
+        let root = temp_dir("root");
+        let err = unconfined(&root, false).unwrap_err().to_string();
+        assert!(err.contains("--allow-unsandboxed"), "{err}");
+        unconfined(&root, true).unwrap();
+    }
+}
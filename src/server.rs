@@ -0,0 +1,605 @@
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
+use axum::{Json, Router, extract::{DefaultBodyLimit, Path, State}, http::{HeaderMap, StatusCode, header}, response::{IntoResponse, Response}, routing::{get, put}};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use crate::{auth::{self, AuthError, Scope}, blob::{self, BlobStore}, orm::{self, models::Model}, provenance::{self, PROVENANCE_EXTENSION}, repo::{Index, IndexEntry, MANIFEST_SIDECAR, ManifestSidecar}, signatures::{self, SignatureSet}, status::PackageStatus, storage, translog::{self, PROOF_EXTENSION}, tuf};
+
+// largest accepted upload body
+const MAX_UPLOAD: usize = 512 * 1024 * 1024;
+
+// signed index and its entries, cached with the package list they were built from
+type IndexCache = Arc<Mutex<Option<(String, Vec<u8>, Vec<IndexEntry>)>>>;
+
+#[derive(Clone)]
+pub struct ServerState {
+    conn: DatabaseConnection,
+    store: Arc<dyn BlobStore>,
+    index: IndexCache,
+    writable: bool,
+}
+
+// body of a publish request, binary fields are base64
+#[derive(Serialize, Deserialize)]
+pub struct UploadRequest {
+    pub package: String,
+    pub signature: String,
+    // signed by a signer of the package, its dependencies end up in the index
+    pub manifest: Option<tuf::Signed<ManifestSidecar>>,
+    // signed build provenance, base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UploadResponse {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub signer: String,
+    // position in the transparency log of the registry
+    #[serde(default)]
+    pub log_index: Option<u64>,
+}
+
+// package metadata returned by the API
+#[derive(Serialize)]
+struct PackageInfo {
+    name: String,
+    version: String,
+    author: Option<String>,
+    hash: Option<String>,
+    signed: bool,
+    created_at: DateTime<Utc>,
+    file: Option<String>,
+    #[serde(flatten)]
+    status: PackageStatus,
+}
+
+// error turned into an HTTP status and message
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+impl From<sea_orm::DbErr> for ApiError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}"))
+    }
+}
+
+// expose the local DB and package store over HTTP, read-only unless writable
+pub async fn serve(conn: DatabaseConnection, addr: SocketAddr, writable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let store = blob::open()?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let mode = if writable { "publishing enabled" } else { "read-only" };
+    println!("🌐 Serving packages on http://{} ({mode}), blobs in {}", listener.local_addr()?, store.describe());
+
+    axum::serve(listener, router(conn, store, writable)).await?;
+    Ok(())
+}
+
+pub fn router(conn: DatabaseConnection, store: Arc<dyn BlobStore>, writable: bool) -> Router {
+    let state = ServerState { conn, store, index: Arc::new(Mutex::new(None)), writable };
+
+    Router::new()
+        .route("/index.json", get(index))
+        .route("/log.json", get(log))
+        .route("/{file}", get(metadata))
+        .route("/files/{file}", get(download))
+        .route("/api/packages", get(list_packages))
+        .route("/api/packages/{name}/{version}", get(package_info).put(upload))
+        .route("/api/packages/{name}/{version}/status", put(set_status))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD))
+        .with_state(state)
+}
+
+// check the server accepts writes and the bearer token holds scope
+async fn authorize(state: &ServerState, headers: &HeaderMap, scope: Scope) -> Result<orm::tokens::Model, ApiError> {
+    if !state.writable {
+        return Err(ApiError(StatusCode::FORBIDDEN, "Registry is read-only".into()));
+    }
+    let authorization = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    auth::authorize(&state.conn, authorization, scope).await.map_err(|e| match e {
+        AuthError::Unauthenticated(msg) => ApiError(StatusCode::UNAUTHORIZED, msg),
+        AuthError::Forbidden(msg) => ApiError(StatusCode::FORBIDDEN, msg),
+    })
+}
+
+// names and versions become file names, keep them to a safe charset
+fn valid_name(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with('.')
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'))
+}
+
+// accept a package signed by a key of the server trust store
+async fn upload(
+    State(state): State<ServerState>,
+    Path((name, version)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<UploadRequest>,
+) -> Result<(StatusCode, Json<UploadResponse>), ApiError> {
+    let token = authorize(&state, &headers, Scope::Publish).await?;
+    let bad_request = |msg: String| ApiError(StatusCode::BAD_REQUEST, msg);
+
+    if !valid_name(&name) || !valid_name(&version) {
+        return Err(bad_request(format!("Invalid package name or version: {name} {version}")));
+    }
+    if orm::publish_fn::find_pkg(&state.conn, &name, &version).await?.is_some() {
+        return Err(ApiError(StatusCode::CONFLICT, format!("{name} {version} already exists")));
+    }
+    // names and versions may contain '-', so another package can own the same blob key
+    let pkg_key = blob::pkg_key(&name, &version);
+    let taken = state.store.exists(&pkg_key).await.map_err(|e| e.to_string());
+    if taken.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error reading storage: {e}")))? {
+        return Err(ApiError(StatusCode::CONFLICT, format!("{pkg_key} is already stored for another package")));
+    }
+
+    let pkg_data = general_purpose::STANDARD.decode(&req.package).map_err(|e| bad_request(format!("Invalid package encoding: {e}")))?;
+    let signature = general_purpose::STANDARD.decode(&req.signature).map_err(|e| bad_request(format!("Invalid signature encoding: {e}")))?;
+    // re-verify the signatures against this server's trust store and signature policies
+    let signers = signatures::verify(&name, &pkg_data, &SignatureSet::parse(&signature)).map_err(|e| e.to_string());
+    let signers = signers.map_err(|e| ApiError(StatusCode::UNPROCESSABLE_ENTITY, format!("Signature rejected: {e}")))?;
+    let signer = signers.join(", ");
+
+    // the server cannot decrypt the package, the manifest is only taken from a signer of it
+    let manifest = match req.manifest {
+        Some(sidecar) => {
+            let verified = ManifestSidecar::verify(sidecar.clone(), &pkg_data, &signers).map_err(|e| e.to_string());
+            let manifest = verified.map_err(|e| ApiError(StatusCode::UNPROCESSABLE_ENTITY, format!("Manifest rejected: {e}")))?;
+            if manifest.name != name || manifest.version != version {
+                return Err(bad_request("Manifest does not match package name and version".into()));
+            }
+            Some((manifest, sidecar))
+        }
+        None => None,
+    };
+
+    // provenance must be signed by a trusted key and describe this very package
+    let statement = match &req.provenance {
+        Some(encoded) => {
+            let data = general_purpose::STANDARD.decode(encoded).map_err(|e| bad_request(format!("Invalid provenance encoding: {e}")))?;
+            let checked = provenance::verify(&data, &pkg_data, None).map(|_| ()).map_err(|e| e.to_string());
+            checked.map_err(|e| ApiError(StatusCode::UNPROCESSABLE_ENTITY, format!("Provenance rejected: {e}")))?;
+            Some(data)
+        }
+        None => None,
+    };
+
+    let sha256 = hex::encode(Sha256::digest(&pkg_data));
+    let mut blobs = vec![(pkg_key.clone(), pkg_data), (blob::sibling(&pkg_key, "sig"), signature.clone())];
+    if let Some((_, sidecar)) = &manifest {
+        let data = serde_json::to_vec_pretty(sidecar).map_err(|e| bad_request(e.to_string()))?;
+        blobs.push((blob::sibling(&pkg_key, MANIFEST_SIDECAR), data));
+    }
+    if let Some(data) = statement {
+        blobs.push((blob::sibling(&pkg_key, PROVENANCE_EXTENSION), data));
+    }
+    for (key, data) in blobs {
+        let stored = state.store.put(&key, data).await.map_err(|e| e.to_string());
+        stored.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error storing package: {e}")))?;
+    }
+
+    let author = manifest.and_then(|(m, _)| m.author);
+    let inserted = orm::publish_fn::insert_package(&state.conn, name.clone(), version.clone(), author, Some(sha256.clone()), Some(pkg_key.clone())).await;
+    if let Err(e) = inserted {
+        // do not leave blobs without a DB row behind
+        for extension in ["pkg", "sig", MANIFEST_SIDECAR, PROVENANCE_EXTENSION] {
+            let _ = state.store.delete(&blob::sibling(&pkg_key, extension)).await;
+        }
+        return Err(e.into());
+    }
+    let set = SignatureSet::parse(&signature);
+    orm::publish_fn::update_signature(&state.conn, &name, &version, signature).await?;
+    orm::signatures_fn::replace_signatures(&state.conn, &name, &version, &set.signatures).await?;
+
+    // record the publish in this registry's transparency log
+    let keyids: Vec<String> = set.signatures.into_iter().map(|s| s.keyid).collect();
+    let proof = translog::append(&state.conn, &name, &version, &sha256, &keyids).await.map_err(|e| e.to_string());
+    let proof = proof.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error writing transparency log: {e}")))?;
+    let proof_data = serde_json::to_vec_pretty(&proof).map_err(|e| e.to_string());
+    let proof_data = proof_data.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let stored = state.store.put(&blob::sibling(&pkg_key, PROOF_EXTENSION), proof_data).await.map_err(|e| e.to_string());
+    stored.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error storing inclusion proof: {e}")))?;
+    // local installs and exports read the stored log.json, keep it as current as the proof
+    let log = translog::log_file(&state.conn).await.and_then(|log| Ok(serde_json::to_vec_pretty(&log)?)).map_err(|e| e.to_string());
+    let log = log.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error writing transparency log: {e}")))?;
+    let stored = state.store.put(translog::LOG_FILE, log).await.map_err(|e| e.to_string());
+    stored.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error storing {}: {e}", translog::LOG_FILE)))?;
+
+    println!("📥 {name} {version} published by token {} (signer {signer}, log index {})", token.name, proof.leaf_index);
+    Ok((StatusCode::CREATED, Json(UploadResponse { name, version, sha256, signer, log_index: Some(proof.leaf_index) })))
+}
+
+// yank or deprecate a version, or clear its flags
+async fn set_status(
+    State(state): State<ServerState>,
+    Path((name, version)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(status): Json<PackageStatus>,
+) -> Result<Json<PackageStatus>, ApiError> {
+    let token = authorize(&state, &headers, Scope::Yank).await?;
+    if orm::publish_fn::find_pkg(&state.conn, &name, &version).await?.is_none() {
+        return Err(ApiError(StatusCode::NOT_FOUND, format!("{name} {version} not found")));
+    }
+    orm::status_fn::save_status(&state.conn, &name, &version, status.yanked.clone(), status.deprecated.clone()).await?;
+
+    println!("🏷️ {name} {version} flags set by token {}:{}", token.name, status.tags());
+    Ok(Json(status))
+}
+
+// flags of every version, keyed by (name, version)
+async fn statuses(conn: &DatabaseConnection) -> Result<HashMap<(String, String), PackageStatus>, ApiError> {
+    Ok(orm::status_fn::list_status(conn).await?
+        .into_iter()
+        .map(|row| ((row.name.clone(), row.version.clone()), PackageStatus::from_row(Some(row))))
+        .collect())
+}
+
+// file name a package is served under, also its storage key
+fn file_name(pkg: &Model) -> Option<String> {
+    Some(blob::key_of(pkg.encrypted_path.as_ref()?)).filter(|key| !key.is_empty())
+}
+
+fn package_info_of(pkg: Model, status: PackageStatus) -> PackageInfo {
+    let file = file_name(&pkg).map(|f| format!("files/{f}"));
+    PackageInfo {
+        signed: pkg.signature.is_some(),
+        name: pkg.name,
+        version: pkg.version,
+        author: pkg.author,
+        hash: pkg.hash,
+        created_at: pkg.created_at,
+        file,
+        status,
+    }
+}
+
+// signed index of published packages
+async fn index(State(state): State<ServerState>) -> Result<Response, ApiError> {
+    let body = current_index(&state).await?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+// transparency log with a freshly signed tree head
+async fn log(State(state): State<ServerState>) -> Result<Response, ApiError> {
+    let log = translog::log_file(&state.conn).await.map_err(|e| e.to_string());
+    let log = log.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("Error reading transparency log: {e}")))?;
+    Ok(Json(log).into_response())
+}
+
+// TUF metadata kept in ~/.securepkg/metadata, refreshed on every request
+async fn metadata(State(state): State<ServerState>, Path(file): Path<String>) -> Result<Response, ApiError> {
+    if !tuf::is_metadata_file(&file) {
+        return Err(ApiError(StatusCode::NOT_FOUND, format!("{file} not found")));
+    }
+    current_index(&state).await?;
+
+    let data = tokio::fs::read(storage::get_metadata_dir().join(&file)).await
+        .map_err(|_| ApiError(StatusCode::NOT_FOUND, format!("{file} not found")))?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], data).into_response())
+}
+
+// index.json rebuilt when the DB changes, TUF metadata re-signed when targets change or near expiry
+async fn current_index(state: &ServerState) -> Result<Vec<u8>, ApiError> {
+    let internal = |e: String| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e);
+    let published: Vec<Model> = orm::publish_fn::list_pkg(&state.conn).await?
+        .into_iter()
+        .filter(|p| p.signature.is_some() && p.encrypted_path.is_some())
+        .collect();
+    let statuses = statuses(&state.conn).await?;
+    let status_of = |p: &Model| statuses.get(&(p.name.clone(), p.version.clone())).cloned().unwrap_or_default();
+    let fingerprint = published.iter()
+        .map(|p| format!("{}:{}:{}:{}", p.id, p.hash.as_deref().unwrap_or(""), p.signature.as_deref().unwrap_or(""), status_of(p).tags()))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut cache = state.index.lock().await;
+    let (body, entries) = match cache.as_ref() {
+        Some((cached, body, entries)) if *cached == fingerprint => (body.clone(), entries.clone()),
+        _ => {
+            // entries are described from local files, remote blobs are fetched into the cache
+            let mut files = Vec::new();
+            for pkg in &published {
+                let Some(file) = file_name(pkg) else {
+                    continue;
+                };
+                let path = blob::pkg_path(state.store.as_ref(), &file).await.map_err(|e| e.to_string());
+                match path.map_err(|e| internal(format!("Error reading {file}: {e}")))? {
+                    Some(path) => files.push((pkg.name.clone(), pkg.version.clone(), file, path, status_of(pkg))),
+                    None => eprintln!("⚠️ {file} is missing from {}", state.store.describe()),
+                }
+            }
+
+            let (body, entries) = tokio::task::spawn_blocking(move || build_index(&files))
+                .await
+                .map_err(|e| internal(e.to_string()))?
+                .map_err(|e| internal(format!("Error building index: {e}")))?;
+            *cache = Some((fingerprint, body.clone(), entries.clone()));
+            (body, entries)
+        }
+    };
+
+    // still under the cache lock, so concurrent requests do not race on version counters
+    tokio::task::spawn_blocking(move || tuf::update(&storage::get_metadata_dir(), &entries, &tuf::Lifetimes::SERVER).map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .map_err(|e| internal(format!("Error updating metadata: {e}")))?;
+    Ok(body)
+}
+
+// files are (name, version, file name, local path, yank and deprecation flags)
+fn build_index(files: &[(String, String, String, PathBuf, PackageStatus)]) -> Result<(Vec<u8>, Vec<IndexEntry>), String> {
+    let mut index = Index::default();
+    for (name, version, file, path, status) in files {
+        let mut entry = IndexEntry::from_pkg(name, version, format!("files/{file}"), path)
+            .map_err(|e| e.to_string())?;
+        entry.status = status.clone();
+        index.packages.push(entry);
+    }
+
+    let signed_index = index.sign().map_err(|e| e.to_string())?;
+    let body = serde_json::to_vec_pretty(&signed_index).map_err(|e| e.to_string())?;
+    Ok((body, signed_index.signed.packages))
+}
+
+// .pkg, .sig, .proof or .intoto of a registered package, honouring single byte ranges so clients can resume
+async fn download(State(state): State<ServerState>, Path(file): Path<String>, headers: HeaderMap) -> Result<Response, ApiError> {
+    let not_found = || ApiError(StatusCode::NOT_FOUND, format!("{file} not found"));
+
+    let path = PathBuf::from(&file);
+    let pkg_file = path.with_extension("pkg").to_string_lossy().to_string();
+    let sidecar = match path.extension().and_then(|e| e.to_str()) {
+        Some("pkg") => None,
+        Some(extension @ ("sig" | PROOF_EXTENSION | PROVENANCE_EXTENSION)) => Some(extension.to_string()),
+        _ => return Err(not_found()),
+    };
+
+    let pkg = orm::publish_fn::list_pkg(&state.conn).await?
+        .into_iter()
+        .find(|p| file_name(p).as_deref() == Some(pkg_file.as_str()))
+        .ok_or_else(not_found)?;
+
+    let pkg_key = file_name(&pkg).ok_or_else(not_found)?;
+    let key = match sidecar {
+        Some(extension) => blob::sibling(&pkg_key, &extension),
+        None => pkg_key,
+    };
+    let data = state.store.get(&key).await.map_err(|e| e.to_string());
+    let data = data.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?.ok_or_else(not_found)?;
+
+    let total = data.len();
+    match headers.get(header::RANGE).and_then(|v| v.to_str().ok()).and_then(parse_range) {
+        Some((start, _)) if start >= total => Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{total}"))],
+        ).into_response()),
+        Some((start, end)) => {
+            let end = end.unwrap_or(total - 1).min(total - 1);
+            Ok((
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                    (header::CONTENT_RANGE, format!("bytes {start}-{end}/{total}")),
+                ],
+                data[start..=end].to_vec(),
+            ).into_response())
+        }
+        None => Ok((
+            [(header::CONTENT_TYPE, "application/octet-stream"), (header::ACCEPT_RANGES, "bytes")],
+            data,
+        ).into_response()),
+    }
+}
+
+// "bytes=start-" or "bytes=start-end"
+fn parse_range(value: &str) -> Option<(usize, Option<usize>)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    Some((start, end)).filter(|(start, end)| end.is_none_or(|end| end >= *start))
+}
+
+async fn list_packages(State(state): State<ServerState>) -> Result<Json<Vec<PackageInfo>>, ApiError> {
+    let pkgs = orm::publish_fn::list_pkg(&state.conn).await?;
+    let statuses = statuses(&state.conn).await?;
+    Ok(Json(pkgs.into_iter().map(|pkg| {
+        let status = statuses.get(&(pkg.name.clone(), pkg.version.clone())).cloned().unwrap_or_default();
+        package_info_of(pkg, status)
+    }).collect()))
+}
+
+async fn package_info(State(state): State<ServerState>, Path((name, version)): Path<(String, String)>) -> Result<Json<PackageInfo>, ApiError> {
+    match orm::publish_fn::find_pkg(&state.conn, &name, &version).await? {
+        Some(pkg) => {
+            let status = PackageStatus::from_row(orm::status_fn::find_status(&state.conn, &name, &version).await?);
+            Ok(Json(package_info_of(pkg, status)))
+        }
+        None => Err(ApiError(StatusCode::NOT_FOUND, format!("{name} {version} not found"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{repo::Index, testutil};
+    use reqwest::StatusCode;
+
+    // read-only server over its own DB, with one signed package
+    async fn start(name: &str) -> (String, PathBuf) {
+        testutil::home();
+        let conn = testutil::db().await;
+        let pkg_path = testutil::build_pkg(&conn, name, "1.0.0").await;
+        (testutil::spawn(router(conn, blob::open().unwrap(), false)).await, pkg_path)
+    }
+
+    #[tokio::test]
+    async fn index_lists_signed_packages() {
+        let _serial = testutil::serial().await;
+        let (url, pkg_path) = start("srv-index").await;
+
+        let body = reqwest::get(format!("{url}/index.json")).await.unwrap().bytes().await.unwrap();
+        let index = Index::verify(&body, &url).unwrap();
+        let entry = index.find("srv-index", "1.0.0").unwrap();
+        assert_eq!(entry.file, "files/srv-index-1.0.0.pkg");
+        assert_eq!(entry.size, std::fs::metadata(&pkg_path).unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn download_honours_range() {
+        let (url, pkg_path) = start("srv-range").await;
+        let data = std::fs::read(&pkg_path).unwrap();
+        let client = reqwest::Client::new();
+        let file = format!("{url}/files/srv-range-1.0.0.pkg");
+
+        let full = client.get(&file).send().await.unwrap();
+        assert_eq!(full.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(full.bytes().await.unwrap(), data);
+
+        let part = client.get(&file).header(header::RANGE, "bytes=10-").send().await.unwrap();
+        assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(part.headers()[header::CONTENT_RANGE], format!("bytes 10-{}/{}", data.len() - 1, data.len()));
+        assert_eq!(part.bytes().await.unwrap(), data[10..]);
+
+        let past = client.get(&file).header(header::RANGE, format!("bytes={}-", data.len())).send().await.unwrap();
+        assert_eq!(past.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let sig = client.get(format!("{url}/files/srv-range-1.0.0.sig")).send().await.unwrap();
+        assert_eq!(sig.status(), StatusCode::OK);
+        let other = client.get(format!("{url}/files/srv-range-1.0.0.txt")).send().await.unwrap();
+        assert_eq!(other.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn serves_tuf_metadata() {
+        let _serial = testutil::serial().await;
+        let (url, _) = start("srv-meta").await;
+
+        for file in ["root.json", "1.root.json", "targets.json", "snapshot.json", "timestamp.json"] {
+            let resp = reqwest::get(format!("{url}/{file}")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{file}");
+            let _: serde_json::Value = serde_json::from_slice(&resp.bytes().await.unwrap()).unwrap();
+        }
+        let resp = reqwest::get(format!("{url}/secret.json")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn upload_takes_the_manifest_only_from_a_signer() {
+        testutil::home();
+        let pkg_path = testutil::build_pkg(&testutil::db().await, "srv-upload", "1.0.0").await;
+        let conn = testutil::db().await;
+        let token = auth::create_token(&conn, "ci", &[Scope::Publish]).await.unwrap();
+        // the registry has a store of its own, the build already holds srv-upload-1.0.0.pkg in the local one
+        let store: Arc<dyn BlobStore> = Arc::new(blob::local::LocalStore::new(testutil::temp_dir("srv-upload-store")));
+        let url = testutil::spawn(router(conn, store.clone(), true)).await;
+
+        let package = std::fs::read(&pkg_path).unwrap();
+        let signature = std::fs::read(pkg_path.with_extension("sig")).unwrap();
+        let manifest = crate::manifest::Manifest {
+            name: "srv-upload".into(),
+            version: "1.0.0".into(),
+            dependencies: [("libfoo".to_string(), "^1".to_string())].into(),
+            ..Default::default()
+        };
+        let upload = async |sidecar: tuf::Signed<ManifestSidecar>| {
+            let body = UploadRequest {
+                package: general_purpose::STANDARD.encode(&package),
+                signature: general_purpose::STANDARD.encode(&signature),
+                manifest: Some(sidecar),
+                provenance: None,
+            };
+            reqwest::Client::new().put(format!("{url}/api/packages/srv-upload/1.0.0"))
+                .bearer_auth(&token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&body).unwrap())
+                .send()
+                .await
+                .unwrap()
+                .status()
+        };
+
+        let mut forged = ManifestSidecar::sign(manifest.clone(), &package).unwrap();
+        forged.signed.manifest.dependencies.insert("evil".into(), "*".into());
+        assert_eq!(upload(forged).await, StatusCode::UNPROCESSABLE_ENTITY);
+        let other_package = ManifestSidecar::sign(manifest.clone(), b"another package").unwrap();
+        assert_eq!(upload(other_package).await, StatusCode::UNPROCESSABLE_ENTITY);
+
+        assert_eq!(upload(ManifestSidecar::sign(manifest.clone(), &package).unwrap()).await, StatusCode::CREATED);
+        let stored = store.get(&format!("srv-upload-1.0.0.{MANIFEST_SIDECAR}")).await.unwrap().unwrap();
+        let stored: tuf::Signed<ManifestSidecar> = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(stored.signed.manifest, manifest);
+    }
+
+    #[tokio::test]
+    async fn upload_refuses_a_blob_key_of_another_package() {
+        testutil::home();
+        // srv-dup-1 2 and srv-dup 1-2 both map to srv-dup-1-2.pkg, read each right after it is built
+        let local = testutil::db().await;
+        let mut bodies = Vec::new();
+        for (name, version) in [("srv-dup-1", "2"), ("srv-dup", "1-2")] {
+            let pkg_path = testutil::build_pkg(&local, name, version).await;
+            let package = std::fs::read(&pkg_path).unwrap();
+            let signature = std::fs::read(pkg_path.with_extension("sig")).unwrap();
+            bodies.push((name, version, package, signature));
+        }
+
+        let conn = testutil::db().await;
+        let token = auth::create_token(&conn, "ci", &[Scope::Publish]).await.unwrap();
+        let store: Arc<dyn BlobStore> = Arc::new(blob::local::LocalStore::new(testutil::temp_dir("srv-dup-store")));
+        let url = testutil::spawn(router(conn, store.clone(), true)).await;
+
+        let mut statuses = Vec::new();
+        for (name, version, package, signature) in &bodies {
+            let body = UploadRequest {
+                package: general_purpose::STANDARD.encode(package),
+                signature: general_purpose::STANDARD.encode(signature),
+                manifest: None,
+                provenance: None,
+            };
+            let resp = reqwest::Client::new().put(format!("{url}/api/packages/{name}/{version}"))
+                .bearer_auth(&token)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&body).unwrap())
+                .send()
+                .await
+                .unwrap();
+            statuses.push(resp.status());
+        }
+        assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+        assert_eq!(store.get("srv-dup-1-2.pkg").await.unwrap().unwrap(), bodies[0].2);
+        assert_eq!(store.get("srv-dup-1-2.sig").await.unwrap().unwrap(), bodies[0].3);
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_writes() {
+        let (url, _) = start("srv-readonly").await;
+        let client = reqwest::Client::new();
+        let body = serde_json::to_vec(&UploadRequest { package: String::new(), signature: String::new(), manifest: None, provenance: None }).unwrap();
+
+        let put = client.put(format!("{url}/api/packages/srv-readonly/2.0.0"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(put.status(), StatusCode::FORBIDDEN);
+        let status = client.put(format!("{url}/api/packages/srv-readonly/1.0.0/status"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(status.status(), StatusCode::FORBIDDEN);
+    }
+}
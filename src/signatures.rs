@@ -0,0 +1,234 @@
+use std::{fs, path::Path};
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use crate::{config::{Config, SignaturePolicy}, keys, repo::IndexSignature, translog::{self, Logged}};
+
+// detached signatures of a package, the content of its .sig file
+// a JSON list since co-signing, older packages hold one raw RSA signature
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SignatureSet {
+    pub signatures: Vec<IndexSignature>,
+    // log entry of the package, keys are judged at its time (translog::judged_at)
+    #[serde(skip)]
+    pub logged: Option<Logged>,
+}
+
+// state of one signature of a set, as shown by verify
+pub enum SignatureStatus {
+    Valid,
+    Invalid,
+    UnknownKey,
+    // valid but from a revoked or expired key
+    Rejected(String),
+}
+
+impl SignatureSet {
+    pub fn parse(data: &[u8]) -> Self {
+        match serde_json::from_slice(data) {
+            Ok(set) => set,
+            // legacy raw signature, its key is found by trying trusted keys
+            Err(_) => SignatureSet {
+                signatures: vec![IndexSignature { keyid: String::new(), sig: general_purpose::STANDARD.encode(data), signed_at: None }],
+                logged: None,
+            },
+        }
+    }
+
+    // set stored next to a package, empty when there is none
+    pub fn read(pkg_path: &Path) -> Self {
+        fs::read(pkg_path.with_extension("sig")).map(|data| SignatureSet::parse(&data)).unwrap_or_default()
+    }
+
+    // with the entry of the .proof next to the package, an invalid proof is reported by translog::check
+    pub fn anchored(mut self, pkg_path: &Path, pkg_data: &[u8]) -> Self {
+        self.logged = translog::logged(pkg_path, pkg_data).ok().flatten();
+        self
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    // add the local key's timestamped signature over pkg_data, replacing an older one of the same key
+    pub fn sign(&mut self, pkg_data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+        let keyid = keys::key_id(&keys::load_public_key()?)?;
+        let signed_at = keys::signing_time();
+        let sig = general_purpose::STANDARD.encode(keys::sign_bytes(&keys::timestamped(pkg_data, Some(signed_at)))?);
+
+        // legacy entries have no key id, drop them if they are ours
+        let own = keys::load_public_key()?;
+        self.signatures.retain(|s| {
+            s.keyid != keyid && !(s.keyid.is_empty() && keys::verify_with(&own, pkg_data, &decode(&s.sig)))
+        });
+        self.signatures.push(IndexSignature { keyid: keyid.clone(), sig, signed_at: Some(signed_at) });
+        Ok(keyid)
+    }
+
+    // key id and status of every signature against the trust store, its revocations and expiries
+    // at the log time of the package, or now when no other log vouches for it
+    pub fn check(&self, pkg_data: &[u8]) -> Result<Vec<(String, SignatureStatus)>, Box<dyn std::error::Error>> {
+        let trusted = keys::all_keys()?;
+        let mut statuses = Vec::new();
+        for s in &self.signatures {
+            let sig = decode(&s.sig);
+            let data = keys::timestamped(pkg_data, s.signed_at);
+            let (id, status) = if s.keyid.is_empty() {
+                match trusted.iter().find(|(_, key)| keys::verify_with(key, &data, &sig)) {
+                    Some((id, _)) => (id.clone(), SignatureStatus::Valid),
+                    None => ("unknown".to_string(), SignatureStatus::UnknownKey),
+                }
+            } else {
+                let status = match trusted.iter().find(|(id, _)| *id == s.keyid) {
+                    Some((_, key)) if keys::verify_with(key, &data, &sig) => SignatureStatus::Valid,
+                    Some(_) => SignatureStatus::Invalid,
+                    None => SignatureStatus::UnknownKey,
+                };
+                (s.keyid.clone(), status)
+            };
+
+            let status = match status {
+                SignatureStatus::Valid => match keys::key_problem(&id, Some(translog::judged_at(self.logged.as_ref(), &id)))? {
+                    Some(problem) => SignatureStatus::Rejected(problem),
+                    None => SignatureStatus::Valid,
+                },
+                other => other,
+            };
+            statuses.push((id, status));
+        }
+        Ok(statuses)
+    }
+
+    // distinct trusted keys with a valid signature
+    pub fn trusted_signers(&self, pkg_data: &[u8]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut signers: Vec<String> = Vec::new();
+        for (id, status) in self.check(pkg_data)? {
+            if matches!(status, SignatureStatus::Valid) && !signers.contains(&id) {
+                signers.push(id);
+            }
+        }
+        Ok(signers)
+    }
+}
+
+fn decode(sig: &str) -> Vec<u8> {
+    general_purpose::STANDARD.decode(sig).unwrap_or_default()
+}
+
+// policy of a package, an exact name wins over "*"
+pub fn policy_for(name: &str) -> Result<Option<SignaturePolicy>, Box<dyn std::error::Error>> {
+    let policies = Config::load()?.signature_policies;
+    Ok(policies.iter().find(|p| p.package == name)
+        .or_else(|| policies.iter().find(|p| p.package == "*"))
+        .cloned())
+}
+
+// check signers against the policy of the package, without one a single trusted signature is enough
+pub fn enforce(name: &str, signers: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    match policy_for(name)? {
+        Some(policy) => {
+            let approved: Vec<&String> = signers.iter().filter(|s| policy.keys.contains(s)).collect();
+            if approved.len() < policy.threshold {
+                return Err(format!(
+                    "{name} needs {} of [{}] signatures, found {}",
+                    policy.threshold, policy.keys.join(", "), approved.len()
+                ).into());
+            }
+        }
+        None if signers.is_empty() => return Err("Invalid signature".into()),
+        None => {}
+    }
+    Ok(())
+}
+
+// trusted signers of a package meeting its policy
+pub fn verify(name: &str, pkg_data: &[u8], set: &SignatureSet) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let signers = set.trusted_signers(pkg_data)?;
+    enforce(name, &signers)?;
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{revocation::{Revocation, RevocationReason}, storage, testutil, tuf::Signed};
+    use chrono::{Duration, Utc};
+    use rsa::{RsaPrivateKey, pkcs1::EncodeRsaPublicKey, pkcs8::LineEnding, rand_core::OsRng};
+
+    #[test]
+    fn backdated_signature_of_a_retired_key_needs_another_log() {
+        testutil::home();
+        let key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+        let public_key = key.to_public_key().to_pkcs1_pem(LineEnding::LF).unwrap();
+        let id = keys::key_id(&key.to_public_key()).unwrap();
+        fs::create_dir_all(storage::get_trusted_dir()).unwrap();
+        fs::write(storage::get_trusted_dir().join(format!("{id}.pem")), &public_key).unwrap();
+
+        // retired an hour ago
+        let revoked_at = Utc::now() - Duration::hours(1);
+        let revocation = Revocation { keyid: id.clone(), public_key, reason: RevocationReason::Retired, revoked_at };
+        fs::create_dir_all(storage::get_revoked_dir()).unwrap();
+        let signed = Signed { signed: revocation, signatures: Vec::new() };
+        fs::write(storage::get_revoked_dir().join(format!("{id}.json")), serde_json::to_vec(&signed).unwrap()).unwrap();
+
+        // signed since, claiming a day before
+        let pkg_data = b"package";
+        let signed_at = revoked_at - Duration::days(1);
+        let sig = keys::sign_with(&key, &keys::timestamped(pkg_data, Some(signed_at))).unwrap();
+        let signature = IndexSignature { keyid: id.clone(), sig: general_purpose::STANDARD.encode(sig), signed_at: Some(signed_at) };
+        let mut set = SignatureSet { signatures: vec![signature], logged: None };
+        assert!(set.trusted_signers(pkg_data).unwrap().is_empty());
+
+        // the signer's own log proves nothing
+        set.logged = Some(Logged { log_id: id.clone(), signers: vec![id.clone()], at: signed_at });
+        assert!(set.trusted_signers(pkg_data).unwrap().is_empty());
+
+        // a registry logged it before the key was retired
+        set.logged = Some(Logged { log_id: "registry".into(), signers: vec![id.clone()], at: revoked_at - Duration::minutes(5) });
+        assert_eq!(set.trusted_signers(pkg_data).unwrap(), vec![id]);
+    }
+
+    // trusted throwaway key and a signature of it over data
+    fn trusted_key() -> (RsaPrivateKey, String) {
+        let key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+        let id = keys::key_id(&key.to_public_key()).unwrap();
+        fs::create_dir_all(storage::get_trusted_dir()).unwrap();
+        fs::write(storage::get_trusted_dir().join(format!("{id}.pem")), key.to_public_key().to_pkcs1_pem(LineEnding::LF).unwrap()).unwrap();
+        (key, id)
+    }
+
+    fn signature(key: &RsaPrivateKey, id: &str, data: &[u8], signed_at: chrono::DateTime<Utc>) -> IndexSignature {
+        let sig = keys::sign_with(key, &keys::timestamped(data, Some(signed_at))).unwrap();
+        IndexSignature { keyid: id.to_string(), sig: general_purpose::STANDARD.encode(sig), signed_at: Some(signed_at) }
+    }
+
+    #[tokio::test]
+    async fn threshold_policy_needs_distinct_trusted_keys() {
+        testutil::home();
+        let _serial = testutil::serial().await;
+        let (a, a_id) = trusted_key();
+        let (b, b_id) = trusted_key();
+        let (_, c_id) = trusted_key();
+        let mut config = Config::load().unwrap();
+        config.signature_policies.push(SignaturePolicy { package: "sig-threshold".into(), keys: vec![a_id.clone(), b_id.clone(), c_id], threshold: 2 });
+        config.save().unwrap();
+
+        let pkg_data = b"package";
+        let now = Utc::now();
+        let set = |signatures| SignatureSet { signatures, logged: None };
+
+        let one = set(vec![signature(&a, &a_id, pkg_data, now)]);
+        let err = verify("sig-threshold", pkg_data, &one).unwrap_err().to_string();
+        assert!(err.contains("needs 2 of"), "{err}");
+        assert!(err.ends_with("found 1"), "{err}");
+
+        let same_key = set(vec![signature(&a, &a_id, pkg_data, now), signature(&a, &a_id, pkg_data, now - Duration::minutes(1))]);
+        assert!(verify("sig-threshold", pkg_data, &same_key).unwrap_err().to_string().ends_with("found 1"));
+
+        let two = set(vec![signature(&a, &a_id, pkg_data, now), signature(&b, &b_id, pkg_data, now)]);
+        assert_eq!(verify("sig-threshold", pkg_data, &two).unwrap(), vec![a_id, b_id]);
+
+        let mut config = Config::load().unwrap();
+        config.signature_policies.retain(|p| p.package != "sig-threshold");
+        config.save().unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use crate::{orm, repo, storage};
+
+// yank and deprecation flags of a version, as stored in indexes; reasons may be empty
+// yanked versions are skipped when picking versions but still install when asked for exactly
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PackageStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+}
+
+impl PackageStatus {
+    pub fn from_row(row: Option<orm::status::Model>) -> Self {
+        match row {
+            Some(row) => PackageStatus { yanked: row.yanked, deprecated: row.deprecated },
+            None => PackageStatus::default(),
+        }
+    }
+
+    pub fn is_yanked(&self) -> bool {
+        self.yanked.is_some()
+    }
+
+    // suffix for listings, e.g. " [yanked: broken build]"
+    pub fn tags(&self) -> String {
+        let mut tags = String::new();
+        for (label, reason) in [("yanked", &self.yanked), ("deprecated", &self.deprecated)] {
+            match reason.as_deref() {
+                Some("") => tags.push_str(&format!(" [{label}]")),
+                Some(reason) => tags.push_str(&format!(" [{label}: {reason}]")),
+                None => {}
+            }
+        }
+        tags
+    }
+
+    // printed by install before anything is extracted
+    pub fn warn(&self, name: &str, version: &str) {
+        if let Some(reason) = &self.yanked {
+            eprintln!("🚨🚨 {name} {version} HAS BEEN YANKED{}", suffix(reason));
+            eprintln!("🚨🚨 installing it only because this exact version was requested, nothing will pick it otherwise");
+        }
+        if let Some(reason) = &self.deprecated {
+            eprintln!("⚠️⚠️ {name} {version} IS DEPRECATED{}", suffix(reason));
+        }
+    }
+}
+
+fn suffix(reason: &str) -> String {
+    if reason.is_empty() { String::new() } else { format!(": {reason}") }
+}
+
+// flags recorded in the local DB
+pub async fn load(conn: &DatabaseConnection, name: &str, version: &str) -> Result<PackageStatus, Box<dyn std::error::Error>> {
+    Ok(PackageStatus::from_row(orm::status_fn::find_status(conn, name, version).await?))
+}
+
+// flags of the local DB, completed by the indexes of configured repositories
+pub async fn lookup(conn: &DatabaseConnection, name: &str, version: &str) -> Result<PackageStatus, Box<dyn std::error::Error>> {
+    let mut status = load(conn, name, version).await?;
+    for loaded in repo::load_repositories().await? {
+        if let Some(entry) = loaded.index.find(name, version) {
+            status.yanked = status.yanked.or_else(|| entry.status.yanked.clone());
+            status.deprecated = status.deprecated.or_else(|| entry.status.deprecated.clone());
+        }
+    }
+    Ok(status)
+}
+
+// change the flags of a version of the local DB and of the export folder index listing it
+pub async fn update(
+    conn: &DatabaseConnection,
+    name: &str,
+    version: &str,
+    repo_path: Option<&str>,
+    change: impl FnOnce(&mut PackageStatus),
+) -> Result<PackageStatus, Box<dyn std::error::Error>> {
+    if orm::publish_fn::find_pkg(conn, name, version).await?.is_none() {
+        return Err(format!("{name} {version} not found in database").into());
+    }
+
+    let mut status = load(conn, name, version).await?;
+    change(&mut status);
+    orm::status_fn::save_status(conn, name, version, status.yanked.clone(), status.deprecated.clone()).await?;
+    println!("🗄️ {name} {version} updated in database{}", status.tags());
+
+    // the exports folder is only updated when it lists the version
+    let export_dir = match repo_path {
+        Some(path) => PathBuf::from(path),
+        None => storage::get_securepkg_dir().join("exports"),
+    };
+    let updated = export_dir.join(repo::INDEX_FILE).exists() && repo::set_status(&export_dir, name, version, &status)?;
+    if !updated && repo_path.is_some() {
+        return Err(format!("{name} {version} is not listed in {}", export_dir.display()).into());
+    }
+    Ok(status)
+}
@@ -109,4 +109,62 @@ pub fn get_key_path() -> PathBuf {
 
 pub fn get_pkg_dir() -> PathBuf {
     dirs::home_dir().unwrap().join(".securepkg").join("packages")
-}
\ No newline at end of file
+}
+
+pub fn get_manifests_dir() -> PathBuf {
+    get_securepkg_dir().join("manifests")
+}
+
+pub fn get_installed_dir() -> PathBuf {
+    get_securepkg_dir().join("installed")
+}
+
+pub fn get_current_dir() -> PathBuf {
+    get_installed_dir().join("current")
+}
+
+pub fn get_keys_dir() -> PathBuf {
+    get_securepkg_dir().join("keys")
+}
+
+pub fn get_trusted_dir() -> PathBuf {
+    get_keys_dir().join("trusted")
+}
+
+pub fn get_revoked_dir() -> PathBuf {
+    get_keys_dir().join("revoked")
+}
+
+pub fn get_config_path() -> PathBuf {
+    get_securepkg_dir().join("config.toml")
+}
+
+pub fn get_policy_path() -> PathBuf {
+    get_securepkg_dir().join("policy.toml")
+}
+
+pub fn get_sbom_dir() -> PathBuf {
+    get_securepkg_dir().join("sbom")
+}
+
+pub fn get_cache_dir() -> PathBuf {
+    get_securepkg_dir().join("cache")
+}
+
+// build hook and install script output
+pub fn get_logs_dir() -> PathBuf {
+    get_securepkg_dir().join("logs")
+}
+
+pub fn get_tuf_dir() -> PathBuf {
+    get_securepkg_dir().join("tuf")
+}
+
+pub fn get_metadata_dir() -> PathBuf {
+    get_securepkg_dir().join("metadata")
+}
+
+// last verified head of every transparency log
+pub fn get_translog_dir() -> PathBuf {
+    get_securepkg_dir().join("log")
+}
@@ -0,0 +1,67 @@
+// shared setup of the unit tests
+use std::{fs, net::SocketAddr, path::{Path, PathBuf}, sync::OnceLock};
+use axum::Router;
+use rand::Rng;
+use sea_orm::DatabaseConnection;
+use crate::{archive::{self, ArchiveFormat}, blob, orm, package::{encrypt_archive, sign_stored}, storage};
+
+static HOME: OnceLock<PathBuf> = OnceLock::new();
+// tests writing shared state of the HOME, such as the TUF metadata, run one at a time
+static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+// fresh HOME with keys for the whole test binary, set before any test touches the environment
+pub fn home() -> &'static Path {
+    HOME.get_or_init(|| {
+        let home = std::env::temp_dir().join(format!("securepkg-test-{}", hex::encode(rand::rng().random::<[u8; 8]>())));
+        fs::create_dir_all(&home).unwrap();
+        unsafe { std::env::set_var("HOME", &home) };
+        std::thread::spawn(|| tokio::runtime::Runtime::new().unwrap().block_on(storage::init_local_repo()))
+            .join()
+            .unwrap()
+            .unwrap();
+        home
+    })
+}
+
+pub async fn serial() -> tokio::sync::MutexGuard<'static, ()> {
+    SERIAL.lock().await
+}
+
+// unique scratch dir under the test HOME
+pub fn temp_dir(prefix: &str) -> PathBuf {
+    let dir = home().join(format!("{prefix}-{}", hex::encode(rand::rng().random::<[u8; 8]>())));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// SQLite database of its own for one test
+pub async fn db() -> DatabaseConnection {
+    let path = temp_dir("db").join("db.sqlite");
+    orm::connect(&format!("sqlite://{}?mode=rwc", path.display())).await.unwrap()
+}
+
+// package with one file built, stored and signed the way package build and sign do, returns its local path
+pub async fn build_pkg(conn: &DatabaseConnection, name: &str, version: &str) -> PathBuf {
+    let src = temp_dir("src");
+    fs::write(src.join("hello.txt"), format!("{name} {version}")).unwrap();
+    let plain = temp_dir("plain").join("pkg.tar.zst");
+    archive::pack_dir(&src, &plain, ArchiveFormat::TarZst, &[]).unwrap();
+
+    let pkg_key = blob::pkg_key(name, version);
+    let encrypted = plain.with_extension("pkg");
+    encrypt_archive(&plain, &encrypted, &storage::get_key_path(), ArchiveFormat::TarZst).unwrap();
+    let data = fs::read(&encrypted).unwrap();
+    let hash = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&data));
+    blob::open().unwrap().put(&pkg_key, data).await.unwrap();
+
+    orm::publish_fn::insert_package(conn, name.into(), version.into(), None, Some(hash), Some(pkg_key)).await.unwrap();
+    sign_stored(conn, name, version, false).await.unwrap()
+}
+
+// serve a router on a free localhost port, returns its base url
+pub async fn spawn(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{addr}")
+}
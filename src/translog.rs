@@ -0,0 +1,359 @@
+use std::{fs, path::{Path, PathBuf}};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{blob, keys, merkle::{self, Hash}, orm, repo::IndexSignature, signatures::SignatureSet, storage, tuf::Signed};
+
+// append-only transparency log of publishes, RFC 6962 style:
+// every signature set that can leave this machine is a leaf, the publisher signs tree heads and hands out inclusion proofs,
+// clients remember the largest tree head they saw and demand consistency with it
+
+// published next to index.json: the whole log and its current signed tree head
+pub const LOG_FILE: &str = "log.json";
+// extension of the inclusion proof stored next to .sig
+pub const PROOF_EXTENSION: &str = "proof";
+
+// one publish, leaves are the JSON encoding of this
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub signers: Vec<String>,
+    pub logged_at: DateTime<Utc>,
+}
+
+// size and root of the log at a point in time, signed by the log key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TreeHead {
+    pub log_id: String,
+    pub tree_size: u64,
+    pub root_hash: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+// content of a .proof file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InclusionProof {
+    pub entry: LogEntry,
+    pub leaf_index: u64,
+    pub audit_path: Vec<String>,
+    pub tree_head: Signed<TreeHead>,
+}
+
+// package entry of a verified proof, the time a log vouches for
+#[derive(Debug, Clone)]
+pub struct Logged {
+    pub log_id: String,
+    pub signers: Vec<String>,
+    pub at: DateTime<Utc>,
+}
+
+// content of log.json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogFile {
+    pub tree_head: Signed<TreeHead>,
+    pub entries: Vec<LogEntry>,
+}
+
+impl LogEntry {
+    fn from_row(row: orm::translog::Model) -> Self {
+        LogEntry {
+            name: row.name,
+            version: row.version,
+            sha256: row.sha256,
+            signers: row.signers.split(',').filter(|s| !s.is_empty()).map(String::from).collect(),
+            logged_at: row.logged_at,
+        }
+    }
+
+    pub fn leaf(&self) -> Result<Hash, Box<dyn std::error::Error>> {
+        Ok(merkle::leaf_hash(&serde_json::to_vec(self)?))
+    }
+}
+
+impl TreeHead {
+    fn root(&self) -> Result<Hash, Box<dyn std::error::Error>> {
+        decode_hash(&self.root_hash)
+    }
+}
+
+// append a publish to the local log, returns its inclusion proof against the new tree head
+pub async fn append(conn: &DatabaseConnection, name: &str, version: &str, sha256: &str, signers: &[String]) -> Result<InclusionProof, Box<dyn std::error::Error>> {
+    let row = orm::translog_fn::append_entry(conn, name, version, sha256, signers, keys::signing_time()).await?;
+    let leaf_index = row.leaf_index as u64;
+    let entries = load_entries(conn).await?;
+    let leaves = leaves_of(&entries)?;
+
+    Ok(InclusionProof {
+        entry: entries[leaf_index as usize].clone(),
+        leaf_index,
+        audit_path: merkle::inclusion_proof(&leaves, leaf_index as usize).iter().map(hex::encode).collect(),
+        tree_head: sign_head(&leaves)?,
+    })
+}
+
+// append the current signatures of a package file
+async fn append_file(conn: &DatabaseConnection, name: &str, version: &str, pkg_path: &Path) -> Result<InclusionProof, Box<dyn std::error::Error>> {
+    let pkg_data = fs::read(pkg_path)?;
+    let signers: Vec<String> = SignatureSet::read(pkg_path).signatures.into_iter().map(|s| s.keyid).collect();
+    append(conn, name, version, &hex::encode(Sha256::digest(&pkg_data)), &signers).await
+}
+
+// true when a proof logs exactly this package content and signature set
+pub fn covers(proof_data: &[u8], pkg_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let Ok(proof) = serde_json::from_slice::<InclusionProof>(proof_data) else {
+        return Ok(false);
+    };
+    let signers: Vec<String> = SignatureSet::read(pkg_path).signatures.into_iter().map(|s| s.keyid).collect();
+    Ok(proof.entry.sha256 == hex::encode(Sha256::digest(fs::read(pkg_path)?)) && proof.entry.signers == signers)
+}
+
+// log a package of the storage backend and store its proof next to the .sig, keeping log.json there current
+pub async fn record_publish(conn: &DatabaseConnection, name: &str, version: &str) -> Result<InclusionProof, Box<dyn std::error::Error>> {
+    let pkg = orm::publish_fn::find_pkg(conn, name, version).await?
+        .ok_or("Package not found in database")?;
+    let store = blob::open()?;
+    let pkg_key = blob::key_of(pkg.encrypted_path.as_deref().unwrap_or_default());
+    let pkg_path = blob::pkg_path(store.as_ref(), &pkg_key).await?
+        .ok_or_else(|| format!("{pkg_key} not found in {}", store.describe()))?;
+
+    let proof = append_file(conn, name, version, &pkg_path).await?;
+    let proof_data = serde_json::to_vec_pretty(&proof)?;
+    store.put(&blob::sibling(&pkg_key, PROOF_EXTENSION), proof_data.clone()).await?;
+    store.put(LOG_FILE, serde_json::to_vec_pretty(&log_file(conn).await?)?).await?;
+    if store.local_root().is_none() {
+        fs::write(pkg_path.with_extension(PROOF_EXTENSION), &proof_data)?; // keep the cached copy in sync
+    }
+    Ok(proof)
+}
+
+// log a package file signed outside the storage backend, its proof and log.json are written next to it
+pub async fn record_file(conn: &DatabaseConnection, name: &str, version: &str, pkg_path: &Path) -> Result<InclusionProof, Box<dyn std::error::Error>> {
+    let proof = append_file(conn, name, version, pkg_path).await?;
+    fs::write(pkg_path.with_extension(PROOF_EXTENSION), serde_json::to_vec_pretty(&proof)?)?;
+    let log = serde_json::to_vec_pretty(&log_file(conn).await?)?;
+    fs::write(pkg_path.with_file_name(LOG_FILE), &log)?;
+    blob::open()?.put(LOG_FILE, log).await?;
+    Ok(proof)
+}
+
+// whole log with a freshly signed tree head
+pub async fn log_file(conn: &DatabaseConnection) -> Result<LogFile, Box<dyn std::error::Error>> {
+    let entries = load_entries(conn).await?;
+    Ok(LogFile { tree_head: sign_head(&leaves_of(&entries)?)?, entries })
+}
+
+async fn load_entries(conn: &DatabaseConnection) -> Result<Vec<LogEntry>, Box<dyn std::error::Error>> {
+    Ok(orm::translog_fn::list_entries(conn).await?.into_iter().map(LogEntry::from_row).collect())
+}
+
+fn leaves_of(entries: &[LogEntry]) -> Result<Vec<Hash>, Box<dyn std::error::Error>> {
+    entries.iter().map(LogEntry::leaf).collect()
+}
+
+fn sign_head(leaves: &[Hash]) -> Result<Signed<TreeHead>, Box<dyn std::error::Error>> {
+    let head = TreeHead {
+        log_id: keys::key_id(&keys::load_public_key()?)?,
+        tree_size: leaves.len() as u64,
+        root_hash: hex::encode(merkle::root(leaves)),
+        timestamp: keys::signing_time(),
+    };
+    let payload = serde_json::to_vec(&head)?;
+    let signature = IndexSignature {
+        keyid: head.log_id.clone(),
+        sig: general_purpose::STANDARD.encode(keys::sign_bytes(&payload)?),
+        signed_at: None,
+    };
+    Ok(Signed { signed: head, signatures: vec![signature] })
+}
+
+// tree head signed by its log key, which must be trusted
+fn verify_head(signed: &Signed<TreeHead>) -> Result<TreeHead, Box<dyn std::error::Error>> {
+    let head = &signed.signed;
+    let payload = serde_json::to_vec(head)?;
+    let trusted = keys::trusted_keys()?;
+    let valid = signed.signatures.iter().any(|s| {
+        let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+        s.keyid == head.log_id && trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &payload, &sig))
+    });
+    if !valid {
+        return Err(format!("Tree head of log {} has no valid signature from a trusted key", head.log_id).into());
+    }
+    Ok(head.clone())
+}
+
+// check the .proof of a package: its inclusion, then consistency of the log with the largest tree head seen so far
+// returns None for packages published before the log existed, a missing proof is an error
+// once the repository publishes a log or a signer has a log this client has seen
+pub fn check(pkg_path: &Path, pkg_data: &[u8]) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let proof_path = pkg_path.with_extension(PROOF_EXTENSION);
+    if !proof_path.exists() {
+        if read_log(pkg_path)?.is_some_and(|log| !log.entries.is_empty()) {
+            return Err(format!("No inclusion proof, but the repository publishes {LOG_FILE}: the package was never logged").into());
+        }
+        let logged = SignatureSet::read(pkg_path).signatures.into_iter()
+            .find(|s| storage::get_translog_dir().join(format!("{}.json", s.keyid)).exists());
+        if let Some(signer) = logged {
+            return Err(format!("No inclusion proof, but signer {} keeps a transparency log: the package was never logged", signer.keyid).into());
+        }
+        return Ok(None);
+    }
+    let proof = read_proof(&proof_path)?;
+    let head = verify_proof(&proof, pkg_data)?;
+    let mut report = format!("included at {} of {} in log {}", proof.leaf_index, head.tree_size, head.log_id);
+
+    // the current log must extend the tree the proof was made against
+    let latest = match read_log(pkg_path)? {
+        Some(log) => {
+            let current = verify_head(&log.tree_head)?;
+            if current.log_id != head.log_id {
+                return Err(format!("{LOG_FILE} belongs to log {}, not {}", current.log_id, head.log_id).into());
+            }
+            let leaves = leaves_of(&log.entries)?;
+            if leaves.len() as u64 != current.tree_size || merkle::root(&leaves) != current.root()? {
+                return Err(format!("{LOG_FILE} entries do not match its signed tree head").into());
+            }
+            check_consistency(&head, &current, &leaves)?;
+            report.push_str(&format!(", consistent with current size {}", current.tree_size));
+            Some((current, leaves))
+        }
+        None => None,
+    };
+
+    // and so must it extend every tree head this client accepted before
+    let state_path = storage::get_translog_dir().join(format!("{}.json", head.log_id));
+    let seen: Option<TreeHead> = match fs::read(&state_path) {
+        Ok(data) => Some(serde_json::from_slice(&data)?),
+        Err(_) => None,
+    };
+    match (&seen, &latest) {
+        (Some(seen), Some((current, leaves))) => {
+            check_consistency(seen, current, leaves)?;
+            save_seen(&state_path, current)?;
+        }
+        (None, Some((current, _))) => save_seen(&state_path, current)?,
+        (Some(seen), None) if head.tree_size > seen.tree_size => {
+            report.push_str(&format!(", no {LOG_FILE} to prove consistency with seen size {}", seen.tree_size));
+        }
+        (Some(seen), None) if head.tree_size == seen.tree_size && head.root_hash != seen.root_hash => {
+            return Err(format!("Log {} forked: two different trees of size {}", head.log_id, head.tree_size).into());
+        }
+        (Some(_), None) => {}
+        (None, None) => save_seen(&state_path, &head)?,
+    }
+    Ok(Some(report))
+}
+
+fn read_proof(path: &Path) -> Result<InclusionProof, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_slice(&fs::read(path)?).map_err(|e| format!("Invalid inclusion proof: {e}"))?)
+}
+
+// proof of the package content under a trusted tree head, returns that head
+fn verify_proof(proof: &InclusionProof, pkg_data: &[u8]) -> Result<TreeHead, Box<dyn std::error::Error>> {
+    if proof.entry.sha256 != hex::encode(Sha256::digest(pkg_data)) {
+        return Err("Transparency log entry does not match the package hash".into());
+    }
+    let head = verify_head(&proof.tree_head)?;
+    let audit_path = proof.audit_path.iter().map(|h| decode_hash(h)).collect::<Result<Vec<_>, _>>()?;
+    if !merkle::verify_inclusion(&proof.entry.leaf()?, proof.leaf_index, head.tree_size, &audit_path, &head.root()?) {
+        return Err(format!("Package is not included in log {} at size {}", head.log_id, head.tree_size).into());
+    }
+    Ok(head)
+}
+
+// entry of the .proof next to a package once it verifies, None without a proof
+pub fn logged(pkg_path: &Path, pkg_data: &[u8]) -> Result<Option<Logged>, Box<dyn std::error::Error>> {
+    let proof_path = pkg_path.with_extension(PROOF_EXTENSION);
+    if !proof_path.exists() {
+        return Ok(None);
+    }
+    let proof = read_proof(&proof_path)?;
+    let head = verify_proof(&proof, pkg_data)?;
+    Ok(Some(Logged { log_id: head.log_id, signers: proof.entry.signers, at: proof.entry.logged_at }))
+}
+
+// time the key of signer is judged at: signed_at and build times are asserted by the signer,
+// who could backdate them past an expiry or retirement of the key, so only the entry time
+// in a log run by another key counts, without one the key has to be valid now
+pub fn judged_at(logged: Option<&Logged>, signer: &str) -> DateTime<Utc> {
+    match logged {
+        Some(logged) if logged.log_id != signer && logged.signers.iter().any(|s| s == signer) => logged.at,
+        _ => Utc::now(),
+    }
+}
+
+// old must be a prefix of new, whose leaves are known
+fn check_consistency(old: &TreeHead, new: &TreeHead, leaves: &[Hash]) -> Result<(), Box<dyn std::error::Error>> {
+    if old.tree_size > new.tree_size {
+        return Err(format!(
+            "Log {} shrank from {} to {} entries, published packages were removed",
+            new.log_id, old.tree_size, new.tree_size
+        ).into());
+    }
+    let proof = merkle::consistency_proof(leaves, old.tree_size as usize);
+    if !merkle::verify_consistency(old.tree_size, new.tree_size, &old.root()?, &new.root()?, &proof) {
+        return Err(format!("Log {} at size {} is not an extension of its tree at size {}", new.log_id, new.tree_size, old.tree_size).into());
+    }
+    Ok(())
+}
+
+fn save_seen(path: &Path, head: &TreeHead) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(storage::get_translog_dir())?;
+    fs::write(path, serde_json::to_vec_pretty(head)?)?;
+    Ok(())
+}
+
+// log.json fetched next to a cached package, or at the root of its repository
+fn read_log(pkg_path: &Path) -> Result<Option<LogFile>, Box<dyn std::error::Error>> {
+    let candidates: [PathBuf; 2] = [pkg_path.with_extension(LOG_FILE), pkg_path.with_file_name(LOG_FILE)];
+    match candidates.iter().find(|path| path.exists()) {
+        Some(path) => Ok(Some(serde_json::from_slice(&fs::read(path)?).map_err(|e| format!("Invalid {LOG_FILE}: {e}"))?)),
+        None => Ok(None),
+    }
+}
+
+fn decode_hash(value: &str) -> Result<Hash, Box<dyn std::error::Error>> {
+    hex::decode(value)?.try_into().map_err(|_| format!("Invalid hash {value}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    #[tokio::test]
+    async fn missing_proof_is_refused_once_a_log_exists() {
+        let _serial = testutil::serial().await;
+        let conn = testutil::db().await;
+        let stored = testutil::build_pkg(&conn, "tl-required", "1.0.0").await;
+        // every test DB logs under the same key, forget the tree heads seen from the others
+        let _ = fs::remove_dir_all(storage::get_translog_dir());
+
+        let dir = testutil::temp_dir("tl");
+        let pkg_path = dir.join("tl-required-1.0.0.pkg");
+        for extension in ["pkg", "sig", PROOF_EXTENSION] {
+            fs::copy(stored.with_extension(extension), pkg_path.with_extension(extension)).unwrap();
+        }
+        fs::write(dir.join(LOG_FILE), serde_json::to_vec(&log_file(&conn).await.unwrap()).unwrap()).unwrap();
+        let data = fs::read(&pkg_path).unwrap();
+        let report = check(&pkg_path, &data).unwrap().unwrap();
+        assert!(report.starts_with("included at 0 of 1"), "{report}");
+
+        // the pair copied into the repository without its proof
+        fs::remove_file(pkg_path.with_extension(PROOF_EXTENSION)).unwrap();
+        let err = check(&pkg_path, &data).unwrap_err().to_string();
+        assert!(err.contains(LOG_FILE), "{err}");
+
+        // no log next to it, but the log of its signer was seen
+        fs::remove_file(dir.join(LOG_FILE)).unwrap();
+        let err = check(&pkg_path, &data).unwrap_err().to_string();
+        assert!(err.contains("signer"), "{err}");
+
+        // from before the log
+        fs::remove_dir_all(storage::get_translog_dir()).unwrap();
+        assert!(check(&pkg_path, &data).unwrap().is_none());
+    }
+}
@@ -0,0 +1,558 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Duration, Utc};
+use rsa::{RsaPrivateKey, RsaPublicKey, pkcs1::{DecodeRsaPublicKey, EncodeRsaPublicKey}, pkcs8::LineEnding};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use crate::{config::Config, keys, repo::{IndexEntry, IndexSignature, RepoSource}, storage};
+
+// repository metadata following The Update Framework:
+// root lists the keys of every role, targets the packages, snapshot pins the
+// targets version and timestamp pins the snapshot, each with a version counter and expiry
+
+pub const ROOT: &str = "root";
+pub const TARGETS: &str = "targets";
+pub const SNAPSHOT: &str = "snapshot";
+pub const TIMESTAMP: &str = "timestamp";
+
+// metadata of a repository failed verification: expired, rolled back or badly signed
+// unlike an unreachable repository this is never skipped over
+#[derive(Debug)]
+pub struct MetadataError(pub String);
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+fn fail(message: String) -> Box<dyn std::error::Error> {
+    Box::new(MetadataError(message))
+}
+
+// how long freshly signed roles stay valid, in days
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lifetimes {
+    pub root: i64,
+    pub targets: i64,
+    pub snapshot: i64,
+    pub timestamp: i64,
+}
+
+impl Lifetimes {
+    // serve re-signs on requests, so a frozen server is noticed within a day
+    pub const SERVER: Lifetimes = Lifetimes { root: 365, targets: 90, snapshot: 7, timestamp: 1 };
+
+    // export folders are only re-signed by export, yank and repo refresh, 30 days unless the [tuf] config says otherwise
+    pub fn export() -> Result<Lifetimes, Box<dyn std::error::Error>> {
+        let tuf = Config::load()?.tuf;
+        let snapshot = tuf.export_snapshot_days.unwrap_or(30);
+        let timestamp = tuf.export_timestamp_days.unwrap_or(30);
+        if snapshot < 1 || timestamp < 1 {
+            return Err("export_snapshot_days and export_timestamp_days in config.toml must be at least 1".into());
+        }
+        Ok(Lifetimes { root: 365, targets: snapshot.max(90), snapshot, timestamp })
+    }
+}
+
+// metadata file with detached signatures over the JSON encoding of signed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Signed<T> {
+    pub signed: T,
+    pub signatures: Vec<IndexSignature>,
+}
+
+// fields shared by every role, _type stops one role being replayed as another
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Header {
+    #[serde(rename = "_type")]
+    pub kind: String,
+    pub version: u64,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleKeys {
+    pub keyids: Vec<String>,
+    pub threshold: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Root {
+    #[serde(flatten)]
+    pub header: Header,
+    // key id -> PKCS#1 PEM
+    pub keys: BTreeMap<String, String>,
+    pub roles: BTreeMap<String, RoleKeys>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Targets {
+    #[serde(flatten)]
+    pub header: Header,
+    pub targets: Vec<IndexEntry>,
+}
+
+// version and hash of a metadata file pinned by snapshot or timestamp
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetaFile {
+    pub version: u64,
+    pub length: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    #[serde(flatten)]
+    pub header: Header,
+    pub meta: BTreeMap<String, MetaFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Timestamp {
+    #[serde(flatten)]
+    pub header: Header,
+    pub meta: BTreeMap<String, MetaFile>,
+}
+
+pub trait Role: Serialize + DeserializeOwned + Clone {
+    const NAME: &'static str;
+    // how long a freshly signed file stays valid
+    fn days(lifetimes: &Lifetimes) -> i64;
+
+    fn header(&self) -> &Header;
+
+    fn file() -> String {
+        format!("{}.json", Self::NAME)
+    }
+}
+
+macro_rules! role {
+    ($ty:ty, $name:expr, $field:ident) => {
+        impl Role for $ty {
+            const NAME: &'static str = $name;
+
+            fn days(lifetimes: &Lifetimes) -> i64 {
+                lifetimes.$field
+            }
+
+            fn header(&self) -> &Header {
+                &self.header
+            }
+        }
+    };
+}
+
+role!(Root, ROOT, root);
+role!(Targets, TARGETS, targets);
+role!(Snapshot, SNAPSHOT, snapshot);
+role!(Timestamp, TIMESTAMP, timestamp);
+
+fn header<T: Role>(version: u64, lifetimes: &Lifetimes) -> Header {
+    Header { kind: T::NAME.to_string(), version, expires: Utc::now() + Duration::days(T::days(lifetimes)) }
+}
+
+// re-signed once less than half of the lifetime is left
+fn is_stale<T: Role>(meta: &T, lifetimes: &Lifetimes) -> bool {
+    meta.header().expires - Utc::now() < Duration::days(T::days(lifetimes)) / 2
+}
+
+fn meta_file(version: u64, data: &[u8]) -> MetaFile {
+    MetaFile { version, length: data.len() as u64, sha256: hex::encode(Sha256::digest(data)) }
+}
+
+fn sign<T: Role>(meta: T, signing_keys: &[&RsaPrivateKey]) -> Result<Signed<T>, Box<dyn std::error::Error>> {
+    let payload = serde_json::to_vec(&meta)?;
+    let mut signatures = Vec::new();
+    for key in signing_keys {
+        signatures.push(IndexSignature {
+            keyid: keys::key_id(&key.to_public_key())?,
+            sig: general_purpose::STANDARD.encode(keys::sign_with(key, &payload)?),
+            signed_at: None,
+        });
+    }
+    Ok(Signed { signed: meta, signatures })
+}
+
+// signatures of data from distinct keys of the role, at least the role threshold
+pub fn verify<T: Role>(data: &[u8], root: &Root) -> Result<T, Box<dyn std::error::Error>> {
+    let file = T::file();
+    let signed: Signed<T> = serde_json::from_slice(data).map_err(|e| fail(format!("Invalid {file}: {e}")))?;
+    if signed.signed.header().kind != T::NAME {
+        return Err(fail(format!("{file} holds {} metadata", signed.signed.header().kind)));
+    }
+
+    let role = root.roles.get(T::NAME).ok_or_else(|| fail(format!("Root has no {} role", T::NAME)))?;
+    let payload = serde_json::to_vec(&signed.signed)?;
+    let mut valid: Vec<&str> = Vec::new();
+    for s in &signed.signatures {
+        if valid.contains(&s.keyid.as_str()) || !role.keyids.contains(&s.keyid) {
+            continue;
+        }
+        // revocations and expiry known locally apply to role keys too
+        if keys::key_problem(&s.keyid, Some(Utc::now()))?.is_some() {
+            continue;
+        }
+        let Some(key) = root.keys.get(&s.keyid).and_then(|pem| RsaPublicKey::from_pkcs1_pem(pem).ok()) else {
+            continue;
+        };
+        let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+        if keys::verify_with(&key, &payload, &sig) {
+            valid.push(&s.keyid);
+        }
+    }
+
+    if role.threshold == 0 || valid.len() < role.threshold {
+        return Err(fail(format!("{file} has {} of {} required {} signatures", valid.len(), role.threshold, T::NAME)));
+    }
+    Ok(signed.signed)
+}
+
+fn check_expiry<T: Role>(meta: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let expires = meta.header().expires;
+    if expires < Utc::now() {
+        return Err(fail(format!(
+            "{} expired at {expires}, the publisher has to re-sign the repository with `securepkg repo refresh <dir>` (or it is frozen)",
+            T::file()
+        )));
+    }
+    Ok(())
+}
+
+fn check_rollback<T: Role>(new: &T, trusted: Option<&T>) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(trusted) = trusted && new.header().version < trusted.header().version {
+        return Err(fail(format!(
+            "Rollback detected: {} version {} is older than trusted version {}",
+            T::file(), new.header().version, trusted.header().version
+        )));
+    }
+    Ok(())
+}
+
+fn check_pinned(file: &str, data: &[u8], pinned: Option<&MetaFile>) -> Result<MetaFile, Box<dyn std::error::Error>> {
+    let pinned = pinned.ok_or_else(|| fail(format!("{file} is not listed in the signed metadata")))?;
+    if data.len() as u64 != pinned.length || hex::encode(Sha256::digest(data)) != pinned.sha256 {
+        return Err(fail(format!("{file} does not match the hash pinned by the signed metadata")));
+    }
+    Ok(pinned.clone())
+}
+
+// role of a metadata dir written by this machine, not verified
+fn read_local<T: Role>(dir: &Path) -> Option<(T, Vec<u8>)> {
+    let data = fs::read(dir.join(T::file())).ok()?;
+    let signed: Signed<T> = serde_json::from_slice(&data).ok()?;
+    Some((signed.signed, data))
+}
+
+// sign and write atomically, returns the written bytes
+fn write_role<T: Role>(dir: &Path, file: &str, meta: T, signing_keys: &[&RsaPrivateKey]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let data = serde_json::to_vec_pretty(&sign(meta, signing_keys)?)?;
+    let tmp_path = dir.join(format!("{file}.tmp"));
+    fs::write(&tmp_path, &data)?;
+    fs::rename(&tmp_path, dir.join(file))?;
+    Ok(data)
+}
+
+// root listing key for every role, versioned copies let clients walk the chain of root rotations
+fn write_root(dir: &Path, version: u64, key: &RsaPublicKey, signing_keys: &[&RsaPrivateKey], lifetimes: &Lifetimes) -> Result<(), Box<dyn std::error::Error>> {
+    let keyid = keys::key_id(key)?;
+    let role = RoleKeys { keyids: vec![keyid.clone()], threshold: 1 };
+    let root = Root {
+        header: header::<Root>(version, lifetimes),
+        keys: BTreeMap::from([(keyid, key.to_pkcs1_pem(LineEnding::LF)?)]),
+        roles: [ROOT, TARGETS, SNAPSHOT, TIMESTAMP].into_iter().map(|r| (r.to_string(), role.clone())).collect(),
+    };
+    let data = write_role(dir, &Root::file(), root, signing_keys)?;
+    fs::write(dir.join(format!("{version}.{}", Root::file())), data)?;
+    Ok(())
+}
+
+// bring the metadata of a repository dir up to date with packages
+// new versions are only signed when targets changed or a role is close to expiry
+pub fn update(dir: &Path, packages: &[IndexEntry], lifetimes: &Lifetimes) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+
+    // every role is signed by the local key
+    let key = keys::signing_key()?;
+    let public = key.to_public_key();
+    let keyid = keys::key_id(&public)?;
+    let root = read_local::<Root>(dir).map(|(root, _)| root);
+    if let Some(root) = &root
+        && !root.roles.get(ROOT).is_some_and(|role| role.keyids.contains(&keyid))
+    {
+        return Err(format!(
+            "Local key {keyid} is not the root key of {}, hand it over with `securepkg repo rotate-root {} --old-key <private.pem>`",
+            dir.display(), dir.display()
+        ).into());
+    }
+    if root.as_ref().is_none_or(|r| is_stale(r, lifetimes)) {
+        write_root(dir, root.map_or(1, |r| r.header.version + 1), &public, &[&key], lifetimes)?;
+    }
+    sign_roles(dir, packages, &key, lifetimes, false)
+}
+
+// hand the root of a repository dir over from old_key to the local key
+// the new root is signed by both, so clients trusting the old root accept it, then every role is re-signed by the local key
+pub fn rotate_root(dir: &Path, packages: &[IndexEntry], old_key: &RsaPrivateKey, lifetimes: &Lifetimes) -> Result<(), Box<dyn std::error::Error>> {
+    let (root, _) = read_local::<Root>(dir).ok_or_else(|| format!("{} has no root.json", dir.display()))?;
+    let old_id = keys::key_id(&old_key.to_public_key())?;
+    if !root.roles.get(ROOT).is_some_and(|role| role.keyids.contains(&old_id)) {
+        return Err(format!("Key {old_id} is not the root key of {}", dir.display()).into());
+    }
+
+    let key = keys::signing_key()?;
+    write_root(dir, root.header.version + 1, &key.to_public_key(), &[old_key, &key], lifetimes)?;
+    sign_roles(dir, packages, &key, lifetimes, true)
+}
+
+// targets, snapshot and timestamp, re-signed when changed, stale or forced
+fn sign_roles(dir: &Path, packages: &[IndexEntry], key: &RsaPrivateKey, lifetimes: &Lifetimes, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut packages = packages.to_vec();
+    packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    let targets = read_local::<Targets>(dir);
+    let targets = match targets {
+        Some((old, data)) if !force && old.targets == packages && !is_stale(&old, lifetimes) => (old.header.version, data),
+        old => {
+            let version = old.map_or(1, |(t, _)| t.header.version + 1);
+            let meta = Targets { header: header::<Targets>(version, lifetimes), targets: packages };
+            (version, write_role(dir, &Targets::file(), meta, &[key])?)
+        }
+    };
+    let targets_meta = meta_file(targets.0, &targets.1);
+
+    let snapshot = read_local::<Snapshot>(dir);
+    let snapshot = match snapshot {
+        Some((old, data)) if old.meta.get(&Targets::file()) == Some(&targets_meta) && !is_stale(&old, lifetimes) => (old.header.version, data),
+        old => {
+            let version = old.map_or(1, |(s, _)| s.header.version + 1);
+            let meta = BTreeMap::from([(Targets::file(), targets_meta)]);
+            (version, write_role(dir, &Snapshot::file(), Snapshot { header: header::<Snapshot>(version, lifetimes), meta }, &[key])?)
+        }
+    };
+    let snapshot_meta = meta_file(snapshot.0, &snapshot.1);
+
+    match read_local::<Timestamp>(dir) {
+        Some((old, _)) if old.meta.get(&Snapshot::file()) == Some(&snapshot_meta) && !is_stale(&old, lifetimes) => {}
+        old => {
+            let version = old.map_or(1, |(t, _)| t.header.version + 1);
+            let meta = BTreeMap::from([(Snapshot::file(), snapshot_meta)]);
+            write_role(dir, &Timestamp::file(), Timestamp { header: header::<Timestamp>(version, lifetimes), meta }, &[key])?;
+        }
+    }
+    Ok(())
+}
+
+// metadata last accepted from a repository, kept in ~/.securepkg/tuf/<repo>
+struct TrustedState {
+    dir: std::path::PathBuf,
+}
+
+impl TrustedState {
+    fn load<T: Role>(&self, root: &Root) -> Option<T> {
+        verify(&fs::read(self.dir.join(T::file())).ok()?, root).ok()
+    }
+
+    fn save(&self, file: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let tmp_path = self.dir.join(format!("{file}.tmp"));
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, self.dir.join(file))?;
+        Ok(())
+    }
+}
+
+// verified targets of a repository, None for repositories that never served metadata
+// rejects rolled back versions, expired files and files not pinned by the newer role
+pub async fn refresh_targets(repo_name: &str, source: &RepoSource) -> Result<Option<Targets>, Box<dyn std::error::Error>> {
+    let state = TrustedState { dir: storage::get_tuf_dir().join(repo_name) };
+
+    // first root is trusted when a key of the trust store signed it
+    let (mut root, mut root_data) = match fs::read(state.dir.join(Root::file())) {
+        Ok(data) => (serde_json::from_slice::<Signed<Root>>(&data)?.signed, data),
+        Err(_) => {
+            let Some(data) = source.read(&Root::file()).await? else {
+                return Ok(None);
+            };
+            let candidate: Signed<Root> = serde_json::from_slice(&data)?;
+            let root = verify::<Root>(&data, &candidate.signed)?;
+            let trusted = keys::trusted_keys()?;
+            let payload = serde_json::to_vec(&root)?;
+            let anchored = candidate.signatures.iter().any(|s| {
+                let sig = general_purpose::STANDARD.decode(&s.sig).unwrap_or_default();
+                trusted.iter().any(|(id, key)| *id == s.keyid && keys::verify_with(key, &payload, &sig))
+            });
+            if !anchored {
+                return Err(fail(format!("root.json of {repo_name} is not signed by a trusted key")));
+            }
+            (root, data)
+        }
+    };
+
+    // versions accepted before, checked with the root they were accepted under so a rotation keeps them
+    let seen_timestamp = state.load::<Timestamp>(&root);
+    let seen_snapshot = state.load::<Snapshot>(&root);
+    let seen_targets = state.load::<Targets>(&root);
+
+    // follow root rotations, each new root is signed by the old and the new root keys
+    loop {
+        let next = root.header.version + 1;
+        let Some(data) = source.read(&format!("{next}.{}", Root::file())).await? else {
+            break;
+        };
+        let new_root = verify::<Root>(&data, &root)?;
+        verify::<Root>(&data, &new_root)?;
+        if new_root.header.version != next {
+            return Err(fail(format!("{next}.root.json holds version {}", new_root.header.version)));
+        }
+        root = new_root;
+        root_data = data;
+    }
+    check_expiry(&root)?;
+
+    let data = source.read(&Timestamp::file()).await?
+        .ok_or_else(|| fail(format!("{repo_name} no longer serves timestamp.json")))?;
+    let timestamp = verify::<Timestamp>(&data, &root)?;
+    check_rollback(&timestamp, seen_timestamp.as_ref())?;
+    check_expiry(&timestamp)?;
+    let timestamp_data = data;
+
+    let data = source.read(&Snapshot::file()).await?
+        .ok_or_else(|| fail(format!("{repo_name} serves no snapshot.json")))?;
+    let pinned = check_pinned(&Snapshot::file(), &data, timestamp.meta.get(&Snapshot::file()))?;
+    let snapshot = verify::<Snapshot>(&data, &root)?;
+    if snapshot.header.version != pinned.version {
+        return Err(fail("snapshot.json version differs from the timestamp".into()));
+    }
+    check_rollback(&snapshot, seen_snapshot.as_ref())?;
+    check_expiry(&snapshot)?;
+    let snapshot_data = data;
+
+    let data = source.read(&Targets::file()).await?
+        .ok_or_else(|| fail(format!("{repo_name} serves no targets.json")))?;
+    let pinned = check_pinned(&Targets::file(), &data, snapshot.meta.get(&Targets::file()))?;
+    let targets = verify::<Targets>(&data, &root)?;
+    if targets.header.version != pinned.version {
+        return Err(fail("targets.json version differs from the snapshot".into()));
+    }
+    check_rollback(&targets, seen_targets.as_ref())?;
+    check_expiry(&targets)?;
+
+    state.save(&Root::file(), &root_data)?;
+    state.save(&Timestamp::file(), &timestamp_data)?;
+    state.save(&Snapshot::file(), &snapshot_data)?;
+    state.save(&Targets::file(), &data)?;
+    Ok(Some(targets))
+}
+
+// metadata file names a repository may serve
+pub fn is_metadata_file(file: &str) -> bool {
+    let role = file.strip_suffix(".json").unwrap_or_default();
+    let role = match role.split_once('.') {
+        Some((version, ROOT)) if version.chars().all(|c| c.is_ascii_digit()) => ROOT,
+        _ => role,
+    };
+    [ROOT, TARGETS, SNAPSHOT, TIMESTAMP].contains(&role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use rsa::rand_core::OsRng;
+
+    const LIFETIMES: Lifetimes = Lifetimes::SERVER;
+
+    // export dir signed by a key of its own, whose root a client already trusts under repo_name
+    fn repo_with_key(repo_name: &str) -> (std::path::PathBuf, RsaPrivateKey, RepoSource) {
+        testutil::home();
+        let dir = testutil::temp_dir("tuf");
+        let key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+        write_root(&dir, 1, &key.to_public_key(), &[&key], &LIFETIMES).unwrap();
+        sign_roles(&dir, &[], &key, &LIFETIMES, false).unwrap();
+
+        let state = storage::get_tuf_dir().join(repo_name);
+        fs::create_dir_all(&state).unwrap();
+        fs::copy(dir.join(Root::file()), state.join(Root::file())).unwrap();
+        (dir.clone(), key, RepoSource::Local(dir))
+    }
+
+    fn trusted_root(repo_name: &str) -> Root {
+        let data = fs::read(storage::get_tuf_dir().join(repo_name).join(Root::file())).unwrap();
+        serde_json::from_slice::<Signed<Root>>(&data).unwrap().signed
+    }
+
+    #[tokio::test]
+    async fn clients_follow_a_root_rotation() {
+        let (dir, old_key, source) = repo_with_key("tuf-rotate");
+        assert!(refresh_targets("tuf-rotate", &source).await.unwrap().is_some());
+
+        let err = update(&dir, &[], &LIFETIMES).unwrap_err().to_string();
+        assert!(err.contains("rotate-root"), "{err}");
+
+        rotate_root(&dir, &[], &old_key, &LIFETIMES).unwrap();
+        let targets = refresh_targets("tuf-rotate", &source).await.unwrap().unwrap();
+        assert_eq!(targets.header.version, 2);
+
+        let local_id = keys::key_id(&keys::load_public_key().unwrap()).unwrap();
+        let root = trusted_root("tuf-rotate");
+        assert_eq!(root.header.version, 2);
+        assert_eq!(root.roles[ROOT].keyids, vec![local_id]);
+        update(&dir, &[], &LIFETIMES).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rollback_protection_survives_a_root_rotation() {
+        let (dir, old_key, source) = repo_with_key("tuf-rotate-rollback");
+        // an old copy of the repository, before the versions below
+        let stale = testutil::temp_dir("tuf");
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, stale.join(path.file_name().unwrap())).unwrap();
+        }
+
+        for _ in 0..3 {
+            sign_roles(&dir, &[], &old_key, &LIFETIMES, true).unwrap();
+        }
+        let targets = refresh_targets("tuf-rotate-rollback", &source).await.unwrap().unwrap();
+        assert_eq!(targets.header.version, 4);
+
+        // the stale copy rotated to a new root signs lower versions than the client has seen
+        rotate_root(&stale, &[], &old_key, &LIFETIMES).unwrap();
+        let err = refresh_targets("tuf-rotate-rollback", &RepoSource::Local(stale)).await.unwrap_err();
+        assert!(err.is::<MetadataError>(), "{err}");
+        assert_eq!(trusted_root("tuf-rotate-rollback").header.version, 1);
+    }
+
+    #[tokio::test]
+    async fn root_not_signed_by_the_old_key_is_rejected() {
+        let (dir, _, source) = repo_with_key("tuf-takeover");
+        let key = keys::load_private_key().unwrap();
+        write_root(&dir, 2, &key.to_public_key(), &[&key], &LIFETIMES).unwrap();
+        sign_roles(&dir, &[], &key, &LIFETIMES, true).unwrap();
+
+        let err = refresh_targets("tuf-takeover", &source).await.unwrap_err();
+        assert!(err.is::<MetadataError>(), "{err}");
+        assert_eq!(trusted_root("tuf-takeover").header.version, 1);
+    }
+
+    #[tokio::test]
+    async fn expired_timestamp_is_a_metadata_error() {
+        let (dir, key, source) = repo_with_key("tuf-expired");
+        let expired = Lifetimes { timestamp: -1, ..LIFETIMES };
+        sign_roles(&dir, &[], &key, &expired, true).unwrap();
+
+        let err = refresh_targets("tuf-expired", &source).await.unwrap_err();
+        assert!(err.is::<MetadataError>(), "{err}");
+        assert!(err.to_string().contains("timestamp.json expired") && err.to_string().contains("repo refresh"), "{err}");
+    }
+
+    #[test]
+    fn export_timestamps_outlive_a_day() {
+        testutil::home();
+        let lifetimes = Lifetimes::export().unwrap();
+        assert_eq!((lifetimes.snapshot, lifetimes.timestamp), (30, 30));
+    }
+}
@@ -0,0 +1,359 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::{Path, PathBuf}};
+use sea_orm::DatabaseConnection;
+use semver::{Version, VersionReq};
+use crate::{blob, manifest::Manifest, orm, package::{activate_version, install_pkg, open_pkg, remove_installed}, repo::{self, Index, IndexEntry, LoadedRepo}, status, storage};
+
+// planned move from the installed version to a newer one
+struct Upgrade {
+    name: String,
+    from: String,
+    to: Version,
+    // dependencies of the new version, from its signed manifest or, before fetching, its index entry
+    deps: BTreeMap<String, String>,
+    // None for repository packages of a dry run, they are only fetched to be installed
+    pkg_path: Option<PathBuf>,
+}
+
+// where a candidate version comes from
+enum Source {
+    File(PathBuf),
+    // index of the repository in the loaded list and its entry
+    Repo(usize, IndexEntry),
+}
+
+// upgrade installed packages to the newest versions allowed by dependency constraints
+pub async fn upgrade_pkgs(conn: &DatabaseConnection, name: Option<&str>, dry_run: bool, allow_scripts: bool, allow_unsandboxed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let installed = latest_installed()?;
+
+    let targets: Vec<&Manifest> = match name {
+        Some(n) => vec![installed.get(n).ok_or_else(|| format!("Package {n} is not installed"))?],
+        None => installed.values().collect(),
+    };
+
+    let targets: Vec<String> = targets.into_iter().map(|m| m.name.clone()).collect();
+    let repos = repo::load_repositories().await?;
+    let plan = plan_upgrades(conn, &targets, &installed, &repos, dry_run).await?;
+
+    if plan.is_empty() {
+        println!("✅ Everything is up to date");
+        return Ok(());
+    }
+
+    println!("📋 Planned upgrades:");
+    for upgrade in &plan {
+        println!("  {} {} -> {}", upgrade.name, upgrade.from, upgrade.to);
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    for upgrade in &plan {
+        let to = upgrade.to.to_string();
+        let active = orm::installed_fn::find_active(conn, &upgrade.name).await?;
+
+        install_pkg(&upgrade.name, &to, upgrade.pkg_path.as_deref(), conn, false, allow_scripts, allow_unsandboxed).await?;
+        if active.is_none_or(|a| a.version == upgrade.from) {
+            activate_version(conn, &upgrade.name, &to).await?;
+        }
+        remove_installed(conn, &upgrade.name, &upgrade.from, allow_unsandboxed).await?;
+        println!("⬆️ {} upgraded {} -> {}", upgrade.name, upgrade.from, to);
+    }
+    Ok(())
+}
+
+// highest installed version of every package
+fn latest_installed() -> Result<BTreeMap<String, Manifest>, Box<dyn std::error::Error>> {
+    let mut latest: BTreeMap<String, Manifest> = BTreeMap::new();
+    for m in Manifest::list_installed()? {
+        let newer = match latest.get(&m.name) {
+            Some(prev) => parse(&m.version) > parse(&prev.version),
+            None => true,
+        };
+        if newer {
+            latest.insert(m.name.clone(), m);
+        }
+    }
+    Ok(latest)
+}
+
+// resolve every target against the versions planned so far, then check the planned set fits together
+async fn plan_upgrades(
+    conn: &DatabaseConnection,
+    targets: &[String],
+    installed: &BTreeMap<String, Manifest>,
+    repos: &[LoadedRepo],
+    dry_run: bool,
+) -> Result<Vec<Upgrade>, Box<dyn std::error::Error>> {
+    let mut planned = installed.clone();
+    let mut plan = Vec::new();
+    for name in targets {
+        let current = installed.get(name).ok_or_else(|| format!("Package {name} is not installed"))?;
+        if let Some(upgrade) = resolve(conn, current, &planned, repos, dry_run).await? {
+            let next = planned.get_mut(name).ok_or_else(|| format!("Package {name} is not installed"))?;
+            next.version = upgrade.to.to_string();
+            next.dependencies = upgrade.deps.clone();
+            plan.push(upgrade);
+        }
+    }
+
+    check_plan(&plan, &planned)?;
+    Ok(plan)
+}
+
+// every constraint touching an upgraded package must hold between the planned versions
+fn check_plan(plan: &[Upgrade], planned: &BTreeMap<String, Manifest>) -> Result<(), Box<dyn std::error::Error>> {
+    let upgraded: BTreeSet<&str> = plan.iter().map(|u| u.name.as_str()).collect();
+    let mut conflicts = Vec::new();
+    for m in planned.values() {
+        for (dep, req) in &m.dependencies {
+            if (upgraded.contains(m.name.as_str()) || upgraded.contains(dep.as_str())) && !satisfied(dep, req, planned) {
+                let found = planned.get(dep).map(|d| d.version.as_str()).unwrap_or("not installed");
+                conflicts.push(format!("{} {} requires {dep} {req}, planned {found}", m.name, m.version));
+            }
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(format!("Planned upgrades do not fit together: {}", conflicts.join("; ")).into());
+    }
+    Ok(())
+}
+
+// pick the newest candidate accepted by dependents and whose own dependencies are installed
+// repository candidates are judged by their index entry, only the chosen one is fetched and verified
+async fn resolve(
+    conn: &DatabaseConnection,
+    current: &Manifest,
+    installed: &BTreeMap<String, Manifest>,
+    repos: &[LoadedRepo],
+    dry_run: bool,
+) -> Result<Option<Upgrade>, Box<dyn std::error::Error>> {
+    let Some(current_version) = parse(&current.version) else {
+        println!("⚠️ {} {}: version is not semver, skipping", current.name, current.version);
+        return Ok(None);
+    };
+
+    // constraints other installed packages put on this one
+    let mut reqs = Vec::new();
+    for other in installed.values().filter(|m| m.name != current.name) {
+        if let Some(req) = other.dependencies.get(&current.name) {
+            let req = VersionReq::parse(req).map_err(|e| format!("{} has an invalid requirement on {}: {e}", other.name, current.name))?;
+            reqs.push((other.name.as_str(), req));
+        }
+    }
+
+    for (version, source) in candidates(conn, &current.name, repos).await?.into_iter().rev() {
+        if version <= current_version {
+            break;
+        }
+        if let Some((dependent, req)) = reqs.iter().find(|(_, req)| !req.matches(&version)) {
+            println!("⏭️ {} {version} rejected: {dependent} requires {req}", current.name);
+            continue;
+        }
+
+        // index dependencies are hints, the signed manifest is checked once the package is fetched
+        let deps = match &source {
+            Source::Repo(_, entry) => Ok(entry.dependencies.clone()),
+            Source::File(path) => package_deps(&current.name, path),
+        };
+        let mut deps = match deps {
+            Ok(deps) => deps,
+            Err(e) => {
+                println!("⏭️ {} {version} rejected: {e}", current.name);
+                continue;
+            }
+        };
+        if let Some(missing) = unmet_dependency(&deps, installed) {
+            println!("⏭️ {} {version} rejected: needs {missing}", current.name);
+            continue;
+        }
+
+        let pkg_path = match source {
+            Source::File(path) => Some(path),
+            Source::Repo(..) if dry_run => None,
+            Source::Repo(i, entry) => {
+                let fetched = match repos[i].verified_path(&entry).await {
+                    Ok(path) => package_deps(&current.name, &path).map(|deps| (path, deps)),
+                    Err(e) => Err(e),
+                };
+                match fetched {
+                    Ok((path, signed)) => {
+                        if let Some(missing) = unmet_dependency(&signed, installed) {
+                            println!("⏭️ {} {version} rejected: signed manifest needs {missing}", current.name);
+                            continue;
+                        }
+                        deps = signed;
+                        Some(path)
+                    }
+                    Err(e) => {
+                        println!("⚠️ {} {version} from {} skipped: {e}", current.name, repos[i].repo.name);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        return Ok(Some(Upgrade {
+            name: current.name.clone(),
+            from: current.version.clone(),
+            to: version,
+            deps,
+            pkg_path,
+        }));
+    }
+    Ok(None)
+}
+
+// dependencies of the signed manifest of a package file
+fn package_deps(name: &str, pkg_path: &Path) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let (_, entries) = open_pkg(name, pkg_path)?;
+    Ok(Manifest::from_entries(&entries)?.map(|m| m.dependencies).unwrap_or_default())
+}
+
+// first dependency not satisfied by installed packages
+fn unmet_dependency(deps: &BTreeMap<String, String>, installed: &BTreeMap<String, Manifest>) -> Option<String> {
+    deps.iter().find(|(dep, req)| !satisfied(dep, req, installed)).map(|(dep, req)| format!("{dep} {req}"))
+}
+
+fn satisfied(dep: &str, req: &str, installed: &BTreeMap<String, Manifest>) -> bool {
+    VersionReq::parse(req).ok().zip(installed.get(dep).and_then(|m| parse(&m.version)))
+        .is_some_and(|(req, version)| req.matches(&version))
+}
+
+// signed versions of a package, from the package storage, the exports folder and configured repositories
+// yanked versions are left out, a version yanked anywhere is yanked everywhere
+async fn candidates(conn: &DatabaseConnection, name: &str, repos: &[LoadedRepo]) -> Result<BTreeMap<Version, Source>, Box<dyn std::error::Error>> {
+    let mut found = BTreeMap::new();
+    let mut yanked: BTreeSet<Version> = BTreeSet::new();
+
+    let store = blob::open()?;
+    for pkg in orm::publish_fn::find_versions(conn, name).await? {
+        if status::load(conn, name, &pkg.version).await?.is_yanked() {
+            yanked.extend(parse(&pkg.version));
+            continue;
+        }
+        if let (Some(version), Some(path)) = (parse(&pkg.version), pkg.encrypted_path) {
+            let key = blob::key_of(&path);
+            if store.exists(&blob::sibling(&key, "sig")).await?
+                && let Some(path) = blob::pkg_path(store.as_ref(), &key).await?
+            {
+                found.insert(version, Source::File(path));
+            }
+        }
+    }
+
+    let exports_dir = storage::get_securepkg_dir().join("exports");
+    if let Ok(index) = Index::load(&exports_dir) {
+        yanked.extend(index.packages.iter().filter(|e| e.name == name && e.status.is_yanked()).filter_map(|e| parse(&e.version)));
+    }
+    if exports_dir.exists() {
+        let prefix = format!("{name}-");
+        for entry in fs::read_dir(&exports_dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let version = file_name.strip_prefix(&prefix).and_then(|v| v.strip_suffix(".pkg")).and_then(parse);
+            if let Some(version) = version.filter(|_| path.with_extension("sig").exists()) {
+                found.entry(version).or_insert(Source::File(path));
+            }
+        }
+    }
+
+    for (i, loaded) in repos.iter().enumerate() {
+        let entries: Vec<&IndexEntry> = loaded.index.packages.iter().filter(|e| e.name == name).collect();
+        yanked.extend(entries.iter().filter(|e| e.status.is_yanked()).filter_map(|e| parse(&e.version)));
+        for entry in entries {
+            if let Some(version) = parse(&entry.version) {
+                found.entry(version).or_insert_with(|| Source::Repo(i, (*entry).clone()));
+            }
+        }
+    }
+    found.retain(|version, _| !yanked.contains(version));
+    Ok(found)
+}
+
+fn parse(version: &str) -> Option<Version> {
+    Version::parse(version).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Repository, repo::RepoSource, status::PackageStatus, testutil};
+
+    fn manifest(name: &str, version: &str, deps: &[(&str, &str)]) -> (String, Manifest) {
+        let dependencies = deps.iter().map(|(d, r)| (d.to_string(), r.to_string())).collect();
+        (name.to_string(), Manifest { name: name.into(), version: version.into(), dependencies, ..Default::default() })
+    }
+
+    fn entry(name: &str, version: &str, deps: &[(&str, &str)]) -> IndexEntry {
+        IndexEntry {
+            name: name.into(),
+            version: version.into(),
+            file: format!("{name}-{version}.pkg"),
+            sha256: String::new(),
+            size: 0,
+            signer: None,
+            dependencies: deps.iter().map(|(d, r)| (d.to_string(), r.to_string())).collect(),
+            status: PackageStatus::default(),
+        }
+    }
+
+    // repository only known by its index, dry runs never fetch from it
+    fn index_repo(packages: Vec<IndexEntry>) -> Vec<LoadedRepo> {
+        vec![LoadedRepo {
+            repo: Repository { name: "test".into(), url: "http://127.0.0.1:9".into() },
+            source: RepoSource::Http("http://127.0.0.1:9".into()),
+            index: Index { updated_at: None, packages },
+        }]
+    }
+
+    #[tokio::test]
+    async fn upgrades_are_resolved_against_the_planned_versions() {
+        testutil::home();
+        let conn = testutil::db().await;
+        let installed: BTreeMap<_, _> = [manifest("upg-a", "1.0.0", &[]), manifest("upg-b", "1.0.0", &[])].into();
+        // upg-b 2.0.0 still wants upg-a 1, which is fine against the installed upg-a but not the planned one
+        let repos = index_repo(vec![
+            entry("upg-a", "2.0.0", &[]),
+            entry("upg-b", "1.5.0", &[("upg-a", ">=1")]),
+            entry("upg-b", "2.0.0", &[("upg-a", "^1")]),
+        ]);
+
+        let targets = ["upg-a".to_string(), "upg-b".to_string()];
+        let plan = plan_upgrades(&conn, &targets, &installed, &repos, true).await.unwrap();
+        let chosen: Vec<(&str, String)> = plan.iter().map(|u| (u.name.as_str(), u.to.to_string())).collect();
+        assert_eq!(chosen, [("upg-a", "2.0.0".to_string()), ("upg-b", "1.5.0".to_string())]);
+
+        // a planned set that does not fit together is refused as a whole
+        let clash = vec![
+            Upgrade { name: "upg-a".into(), from: "1.0.0".into(), to: Version::new(2, 0, 0), deps: BTreeMap::new(), pkg_path: None },
+            Upgrade { name: "upg-b".into(), from: "1.0.0".into(), to: Version::new(2, 0, 0), deps: [("upg-a".to_string(), "^1".to_string())].into(), pkg_path: None },
+        ];
+        let planned: BTreeMap<_, _> = [manifest("upg-a", "2.0.0", &[]), manifest("upg-b", "2.0.0", &[("upg-a", "^1")])].into();
+        let err = check_plan(&clash, &planned).unwrap_err().to_string();
+        assert!(err.contains("upg-b 2.0.0 requires upg-a ^1, planned 2.0.0"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn yanked_versions_are_skipped_but_install_when_pinned() {
+        testutil::home();
+        let conn = testutil::db().await;
+        for version in ["1.0.0", "1.1.0"] {
+            testutil::build_pkg(&conn, "upg-yank", version).await;
+        }
+        orm::status_fn::save_status(&conn, "upg-yank", "1.1.0", Some("broken build".into()), None).await.unwrap();
+        let mut yanked = entry("upg-yank", "1.2.0", &[]);
+        yanked.status.yanked = Some(String::new());
+        let repos = index_repo(vec![yanked]);
+
+        let found: Vec<String> = candidates(&conn, "upg-yank", &repos).await.unwrap().keys().map(|v| v.to_string()).collect();
+        assert_eq!(found, ["1.0.0"]);
+        let installed: BTreeMap<_, _> = [manifest("upg-yank", "0.9.0", &[])].into();
+        let plan = plan_upgrades(&conn, &["upg-yank".to_string()], &installed, &repos, true).await.unwrap();
+        assert_eq!(plan[0].to, Version::new(1, 0, 0));
+
+        // asking for the exact version still installs it
+        install_pkg("upg-yank", "1.1.0", None, &conn, false, false, false).await.unwrap();
+        assert!(storage::get_installed_dir().join("upg-yank-1.1.0/hello.txt").exists());
+        remove_installed(&conn, "upg-yank", "1.1.0", false).await.unwrap();
+    }
+}
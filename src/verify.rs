@@ -0,0 +1,96 @@
+use std::{fs, path::Path};
+use crate::{archive, manifest::{self, Manifest}, package::{decrypt_pkg, locate_pkg}, policy::{self, InstallPolicy}, provenance, signatures::{self, SignatureSet, SignatureStatus}, storage, translog};
+
+// report every signature of a package, its policies and content integrity
+// returns false when the package would be refused at install, explain shows every install policy rule
+pub async fn verify_report(name: &str, version: &str, from_file: Option<&Path>, explain: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let pkg_path = locate_pkg(name, version, from_file).await?;
+    println!("📦 Package: {}", pkg_path.display());
+
+    let pkg_data = fs::read(&pkg_path)?;
+    let set = SignatureSet::read(&pkg_path).anchored(&pkg_path, &pkg_data);
+    if set.signatures.is_empty() {
+        println!("❌ No signature found next to the package");
+        return Ok(false);
+    }
+
+    for (keyid, status) in set.check(&pkg_data)? {
+        match status {
+            SignatureStatus::Valid => println!("✅ {keyid}: valid"),
+            SignatureStatus::Invalid => println!("❌ {keyid}: invalid signature"),
+            SignatureStatus::UnknownKey => println!("⚠️ {keyid}: key not trusted"),
+            SignatureStatus::Rejected(problem) => println!("⛔ {keyid}: key {problem}"),
+        }
+    }
+
+    let mut ok = true;
+    let signers = set.trusted_signers(&pkg_data)?;
+    match signatures::enforce(name, &signers) {
+        Ok(_) => match signatures::policy_for(name)? {
+            Some(policy) => println!("🛡️ Policy met: {} of [{}]", policy.threshold, policy.keys.join(", ")),
+            None => println!("🛡️ No signature policy, {} trusted signer(s)", signers.len()),
+        },
+        Err(e) => {
+            println!("❌ {e}");
+            ok = false;
+        }
+    }
+
+    match translog::check(&pkg_path, &pkg_data) {
+        Ok(Some(report)) => println!("📜 Transparency log: {report}"),
+        Ok(None) => println!("⚠️ No inclusion proof (published before the transparency log)"),
+        Err(e) => {
+            println!("❌ {e}");
+            ok = false;
+        }
+    }
+
+    match provenance::check(&pkg_path, &pkg_data) {
+        Ok(Some(report)) => println!("🏗️ Provenance: {report}"),
+        Ok(None) => println!("⚠️ No provenance (built before provenance was recorded)"),
+        Err(e) => {
+            println!("❌ {e}");
+            ok = false;
+        }
+    }
+
+    // content check, the package is only decrypted in memory
+    let key_bytes = fs::read(storage::get_key_path())?;
+    let (format, decrypted_data) = decrypt_pkg(&pkg_data, &key_bytes)?;
+    let entries = archive::read_entries(&decrypted_data, format)?;
+    let manifest = Manifest::from_entries(&entries)?;
+    match &manifest {
+        Some(m) => match m.verify_files(&manifest::payload_files(&entries)) {
+            Ok(_) => println!("🌳 Merkle root OK, {} files", m.files.len()),
+            Err(e) => {
+                println!("❌ {e}");
+                ok = false;
+            }
+        },
+        None => println!("⚠️ No manifest in package (legacy package)"),
+    }
+    if let Err(e) = provenance::require(&pkg_path, manifest.as_ref()) {
+        println!("❌ {e}");
+        ok = false;
+    }
+
+    match InstallPolicy::load()? {
+        Some(install_policy) => {
+            let results = install_policy.evaluate(&pkg_data, &signers, manifest.as_ref(), &entries)?;
+            if explain {
+                policy::explain(&results);
+            }
+            match policy::enforce(name, &results) {
+                Ok(_) => println!("🛡️ Install policy met"),
+                Err(e) => {
+                    println!("❌ {e}");
+                    ok = false;
+                }
+            }
+        }
+        None if explain => println!("🛡️ No install policy at {}", policy::policy_path().display()),
+        None => {}
+    }
+
+    Ok(ok)
+}